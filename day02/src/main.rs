@@ -1,79 +1,109 @@
+use intcode::{
+    input_arg, limits_arg, run_symbolic, DecodeCache, Limits, Machine, Memory, Program, Status,
+    Var, VmError,
+};
 use std::io;
+use std::process;
 
 fn main() {
-    let mut line = String::new();
-    io::stdin().read_line(&mut line).unwrap();
-
-    let mut nums: Vec<u32> = line
-        .trim()
-        .split(",")
-        .map(|s| s.parse().unwrap())
-        .collect();
+    // the program comes from `--input <file>`, or stdin without it
+    let program = match input_arg() {
+        Some(path) => Program::load(&path).map_err(|e| format!("{}: {}", path, e)),
+        None => Program::from_reader(&mut io::stdin()).map_err(|e| e.to_string()),
+    };
+    let mut nums: Vec<i64> = program
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1)
+        })
+        .into();
+    // `--fuel` and `--time-limit` apply to each run of the search
+    let limits = limits_arg().unwrap_or_else(|e| {
+        eprintln!(
+            "{}\nusage: [--input <program file>] [--fuel <n>] [--time-limit <s>]",
+            e
+        );
+        process::exit(2)
+    });
 
     let desired_program_result = 19690720;
-    let mut noun: u32 = 0;
-    let mut verb: u32 = 0;
+    let (noun, verb) = match solve(&nums, desired_program_result) {
+        Some(solution) => solution,
+        None => search(&mut nums, desired_program_result, limits).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1)
+        }),
+    };
+
+    println!("{}", 100 * noun + verb);
+}
+
+// The result is usually `a * noun + b * verb + c`, which can be solved for without running the
+// program 10,000 times.
+fn solve(nums: &[i64], desired_program_result: i64) -> Option<(i64, i64)> {
+    let result = run_symbolic(nums, &[1, 2], 10_000).ok()?.cell(0).affine()?;
+    let (noun, verb) = (Var::Cell(1), Var::Cell(2));
+    if result.terms.keys().any(|&var| var != noun && var != verb) {
+        return None;
+    }
+    let (a, b) = (result.coefficient(noun), result.coefficient(verb));
+    (0..100).find_map(|n| {
+        let rest = desired_program_result
+            .checked_sub(result.constant)?
+            .checked_sub(a.checked_mul(n)?)?;
+        let v = match b {
+            0 if rest == 0 => 0,
+            0 => return None,
+            _ if rest % b == 0 => rest / b,
+            _ => return None,
+        };
+        (0..100).contains(&v).then_some((n, v))
+    })
+}
+
+fn search(
+    nums: &mut [i64],
+    desired_program_result: i64,
+    limits: Limits,
+) -> Result<(i64, i64), VmError> {
+    let mut noun = 0;
+    let mut verb = 0;
+    // every run is the same program but for its operands, so they can all share one decoding
+    let mut cache = DecodeCache::new();
 
     for n in 0..100 {
         for v in 0..100 {
             nums[1] = n;
             nums[2] = v;
 
-            if run_program(&nums) == desired_program_result {
+            if run_program(nums, &mut cache, limits)? == desired_program_result {
                 noun = n;
                 verb = v;
                 break;
             }
         }
     }
-
-    println!("{}", 100 * noun + verb);
-}
-
-enum OpCode {
-    Add,
-    Mult,
-    Halt
-}
-
-impl OpCode {
-    fn new(i: u32) -> OpCode {
-        match i {
-            1 => OpCode::Add,
-            2 => OpCode::Mult,
-            99 => OpCode::Halt,
-            _ => panic!("{} is an invalid opcode, must be in [1, 2, 99]", i)
-        }
-    }
+    Ok((noun, verb))
 }
 
-fn print_program(nums: &Vec<u32>) {
-    println!("-----------------------------------------------------");
-    for i in (0..nums.len()).step_by(4) {
-        if i+1 < nums.len() {
-            println!("at {} do {} to  {} {} {}", i, nums[i], nums[i+1], nums[i+2], nums[i+3]);
-        } else {
-            println!("at {} do {} -", i, nums[i]);
+fn run_program(nums: &[i64], cache: &mut DecodeCache, limits: Limits) -> Result<i64, VmError> {
+    let mut machine = Machine::new(nums.to_vec());
+    machine.set_decode_cache(Some(std::mem::take(cache)));
+    machine.set_limits(limits);
+    // the result is what's left at address 0, so anything output along the way is ignored
+    let status = loop {
+        match machine.run_until() {
+            Ok(Status::Output(_)) => (),
+            status => break status,
         }
+    };
+    *cache = machine.take_decode_cache().unwrap();
+    match status? {
+        Status::Halted => Ok(machine.data().read(0)),
+        Status::OutOfFuel { address, steps } => Err(VmError::OutOfFuel { address, steps }),
+        // there is no input to give it
+        Status::NeedsInput | Status::Output(_) => Err(VmError::InputExhausted {
+            address: machine.program_counter(),
+        }),
     }
 }
-
-fn run_program(nums_p: &Vec<u32>) -> u32 {
-    let mut nums = nums_p.clone();
-
-    for i in (0..nums.len()-1).step_by(4) {
-        match OpCode::new(nums[i]) {
-            OpCode::Add => {
-                let store_index = nums[i+3];
-                nums[store_index as usize] = nums[nums[i+1] as usize] + nums[nums[i+2] as usize];
-            },
-            OpCode::Mult => {
-                let store_index = nums[i+3];
-                nums[store_index as usize] = nums[nums[i+1] as usize] * nums[nums[i+2] as usize];
-            },
-            OpCode::Halt => break,
-        }
-    }
-
-    nums[0]
-}
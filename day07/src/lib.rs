@@ -1,262 +1,383 @@
-use crate::Addr::*;
-use crate::OpCode::*;
-use std::io::{self, BufRead};
+pub use intcode::*;
+use itertools::Itertools;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
-trait DataAddr {
-    fn getData<'a>(&self, data: &'a mut Vec<i32>) -> &'a mut i32;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wiring {
+    // the last amplifier's output is the result
+    Linear,
+    // the last amplifier feeds back into the first until they halt
+    Feedback,
 }
-#[derive(Debug)]
-pub enum Addr {
-    Immediate(Imm),
-    Position(Pos),
+
+// Run one amplifier per phase setting, each feeding its output to the next, starting with a
+// signal of 0. Returns the last signal out of the last amplifier, and no signal without any
+// phase settings.
+pub fn amplify(program: &[i64], phases: &[i64], wiring: Wiring) -> Result<Option<i64>, VmError> {
+    amplify_with_limits(program, phases, wiring, Limits::default())
 }
-#[derive(Debug)]
-pub struct Imm(i32);
-impl Into<Addr> for Imm {
-    fn into(self) -> Addr {
-        Immediate(Imm(self.0))
+
+// `amplify`, with each amplifier stopping with `OutOfFuel` once it reaches `limits`.
+pub fn amplify_with_limits(
+    program: &[i64],
+    phases: &[i64],
+    wiring: Wiring,
+    limits: Limits,
+) -> Result<Option<i64>, VmError> {
+    // every node used here has just been added, so none of the unwraps below can fail
+    let mut network = Network::new();
+    let amplifiers: Vec<usize> = phases
+        .iter()
+        .map(|&phase| {
+            let mut machine = Machine::new(program.to_vec());
+            machine.set_limits(limits);
+            let amplifier = network.add_machine(machine);
+            network.push_input(amplifier, phase).unwrap();
+            amplifier
+        })
+        .collect();
+    for pair in amplifiers.windows(2) {
+        network.connect(pair[0], pair[1]).unwrap();
     }
-}
-impl DataAddr for Imm {
-    fn getData<'a>(&self, data: &'a mut Vec<i32>) -> &'a mut i32 {
-        data.get_mut(self.0 as usize).unwrap()
+    let (first, last) = match (amplifiers.first(), amplifiers.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return Ok(None),
+    };
+    if wiring == Wiring::Feedback {
+        network.connect(last, first).unwrap();
     }
-}
-#[derive(Debug)]
-pub struct Pos(i32);
-impl DataAddr for Pos {
-    fn getData<'a>(&self, data: &'a mut Vec<i32>) -> &'a mut i32 {
-        let val = *(data.get(self.0 as usize).unwrap()) as usize;
-        data.get_mut(val).unwrap()
+    network.push_input(first, 0).unwrap();
+
+    match network.run()? {
+        Outcome::Halted => Ok(network.outputs(last).unwrap().last().cloned()),
+        Outcome::Quiescent => Err(VmError::InputExhausted {
+            address: amplifiers
+                .iter()
+                .map(|&a| network.machine(a).unwrap())
+                .find(|m| !m.is_halted())
+                .unwrap()
+                .program_counter(),
+        }),
     }
 }
-impl Into<Addr> for &Pos {
-    fn into(self) -> Addr {
-        Position(Pos(self.0))
-    }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhaseResult {
+    pub phases: Vec<i64>,
+    pub signal: Option<i64>,
 }
-impl From<(i32, bool)> for Addr {
-    fn from(tuple: (i32, bool)) -> Addr {
-        let (i, isImmediate) = (tuple.0, tuple.1);
 
-        match isImmediate {
-            true => Immediate(Imm(i)),
-            false => Position(Pos(i)),
-        }
+// Try every ordering of `domain` as phase settings, spread over all available cores. Returns
+// every result, best signal first, and none for an empty domain.
+pub fn search_phases(
+    program: &[i64],
+    domain: &[i64],
+    wiring: Wiring,
+) -> Result<Vec<PhaseResult>, VmError> {
+    search_phases_with_limits(program, domain, wiring, Limits::default())
+}
+
+// `search_phases`, with every amplifier limited as in `amplify_with_limits`, so a program that
+// never halts fails the search rather than hanging it.
+pub fn search_phases_with_limits(
+    program: &[i64],
+    domain: &[i64],
+    wiring: Wiring,
+    limits: Limits,
+) -> Result<Vec<PhaseResult>, VmError> {
+    if domain.is_empty() {
+        return Ok(Vec::new());
     }
+    let permutations: Vec<Vec<i64>> = domain.iter().cloned().permutations(domain.len()).collect();
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(permutations.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let phases = match permutations.get(i) {
+                    Some(phases) => phases,
+                    None => break,
+                };
+                let signal = amplify_with_limits(program, phases, wiring, limits);
+                results
+                    .lock()
+                    .unwrap()
+                    .push(signal.map(|signal| PhaseResult {
+                        phases: phases.clone(),
+                        signal,
+                    }));
+            });
+        }
+    });
+
+    let mut ranked = results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .collect::<Result<Vec<PhaseResult>, VmError>>()?;
+    // ties go to the first phases in lexicographic order, so the ranking doesn't depend on
+    // which thread finished first
+    ranked.sort_by(|a, b| {
+        b.signal
+            .cmp(&a.signal)
+            .then_with(|| a.phases.cmp(&b.phases))
+    });
+    Ok(ranked)
 }
-impl DataAddr for Addr {
-    fn getData<'a>(&self, data: &'a mut Vec<i32>) -> &'a mut i32 {
-        match self {
-            Immediate(Imm(i)) => data.get_mut((*i) as usize).unwrap(),
-            Position(Pos(i)) => {
-                let val = *(data.get((*i) as usize).unwrap()) as usize;
-                data.get_mut(val).unwrap()
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+    use std::time::Duration;
+
+    #[test]
+    fn amplify_linear() {
+        let program = vec![
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ];
+        assert_eq!(
+            amplify(&program, &[4, 3, 2, 1, 0], Wiring::Linear).unwrap(),
+            Some(43210)
+        );
+
+        let program = vec![
+            3, 31, 3, 32, 1002, 32, 10, 32, 1001, 31, -2, 31, 1007, 31, 0, 33, 1002, 33, 7, 33, 1,
+            33, 31, 31, 1, 32, 31, 31, 4, 31, 99, 0, 0, 0,
+        ];
+        assert_eq!(
+            amplify(&program, &[1, 0, 4, 3, 2], Wiring::Linear).unwrap(),
+            Some(65210)
+        );
+    }
+
+    #[test]
+    fn amplify_feedback() {
+        let program = vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        assert_eq!(
+            amplify(&program, &[9, 8, 7, 6, 5], Wiring::Feedback).unwrap(),
+            Some(139629729)
+        );
+    }
+
+    #[test]
+    fn search_linear() {
+        let program = vec![
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ];
+        let ranked = search_phases(&program, &[0, 1, 2, 3, 4], Wiring::Linear).unwrap();
+        assert_eq!(ranked.len(), 120);
+        assert_eq!(
+            ranked[0],
+            PhaseResult {
+                phases: vec![4, 3, 2, 1, 0],
+                signal: Some(43210)
             }
+        );
+        assert_eq!(ranked[119].signal, Some(1234));
+    }
+
+    #[test]
+    fn search_feedback() {
+        let program = vec![
+            3, 52, 1001, 52, -5, 52, 3, 53, 1, 52, 56, 54, 1007, 54, 5, 55, 1005, 55, 26, 1001, 54,
+            -5, 54, 1105, 1, 12, 1, 53, 54, 53, 1008, 54, 0, 55, 1001, 55, 1, 55, 2, 53, 55, 53, 4,
+            53, 1001, 56, -1, 56, 1005, 56, 6, 99, 0, 0, 0, 0, 10,
+        ];
+        let ranked = search_phases(&program, &[5, 6, 7, 8, 9], Wiring::Feedback).unwrap();
+        assert_eq!(ranked[0].phases, vec![9, 7, 8, 5, 6]);
+        assert_eq!(ranked[0].signal, Some(18216));
+    }
+
+    #[test]
+    fn no_phases() {
+        let program = vec![3, 11, 3, 12, 1, 11, 12, 11, 4, 11, 99, 0, 0];
+        assert_eq!(amplify(&program, &[], Wiring::Linear).unwrap(), None);
+        assert_eq!(amplify(&program, &[], Wiring::Feedback).unwrap(), None);
+        assert!(search_phases(&program, &[], Wiring::Feedback)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn limits() {
+        // never halts, and never asks for more input than the phase and the signal
+        let program = vec![3, 9, 3, 9, 1105, 1, 4, 99, 99, 0];
+        let limits = Limits {
+            fuel: Some(1000),
+            ..Limits::default()
+        };
+        assert_eq!(
+            amplify_with_limits(&program, &[1, 2], Wiring::Linear, limits),
+            Err(VmError::OutOfFuel {
+                address: 4,
+                steps: 1000
+            })
+        );
+        let searched = search_phases_with_limits(&program, &[0, 1, 2], Wiring::Feedback, limits);
+        assert!(matches!(searched, Err(VmError::OutOfFuel { .. })));
+
+        let program = vec![3, 11, 3, 12, 1, 11, 12, 11, 4, 11, 99, 0, 0];
+        let limits = Limits {
+            time: Some(Duration::from_secs(60)),
+            ..limits
+        };
+        let ranked = search_phases_with_limits(&program, &[1, 2], Wiring::Linear, limits);
+        assert_eq!(ranked.unwrap()[0].signal, Some(3));
+    }
+
+    #[test]
+    fn day05_basic1() {
+        for input_i in 1..10 {
+            let data = vec![3, 0, 4, 0, 99];
+            let input = vec![input_i];
+            assert_eq!(vec![input_i], run_program(input, data).unwrap())
         }
     }
-}
 
-#[derive(Debug)]
-pub enum OpCode {
-    Add {
-        opAddr1: Addr,
-        opAddr2: Addr,
-        destAddr: Pos,
-    },
-    Mult {
-        opAddr1: Addr,
-        opAddr2: Addr,
-        destAddr: Pos,
-    },
-    Read {
-        destAddr: Pos,
-    },
-    Write {
-        opAddr1: Addr,
-    },
-    JumpIf {
-        boolAddr: Addr,
-        jumpAddr: Addr,
-    },
-    JumpIfNot {
-        boolAddr: Addr,
-        jumpAddr: Addr,
-    },
-    SetIfLt {
-        opAddr1: Addr,
-        opAddr2: Addr,
-        destAddr: Pos,
-    }, // set to 1 or 0
-    SetIfEq {
-        opAddr1: Addr,
-        opAddr2: Addr,
-        destAddr: Pos,
-    },
-    Halt,
-}
-impl From<(&Vec<i32>, i32)> for OpCode {
-    fn from(tuple: (&Vec<i32>, i32)) -> OpCode {
-        let data: &Vec<i32> = tuple.0;
-        let i: i32 = tuple.1;
+    #[test]
+    fn day05_basic2() {
+        for input_i in 6..=10 {
+            let data = vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+            let input = vec![input_i];
+            assert_eq!(
+                vec![if input_i == 8 { 1 } else { 0 }],
+                run_program(input, data).unwrap()
+            );
+        }
+    }
 
-        let opcode = data.get(i as usize).unwrap();
-        let mode2 = ((opcode / 1000) % 10) == 1;
-        let mode1 = ((opcode / 100) % 10) == 1;
-        let op = opcode % 100;
+    #[test]
+    fn day05_basic3() {
+        for input_i in 6..=10 {
+            let data = vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8];
+            let input = vec![input_i];
+            assert_eq!(
+                vec![if input_i < 8 { 1 } else { 0 }],
+                run_program(input, data).unwrap()
+            );
+        }
+    }
 
-        match op {
-            1 => Add {
-                opAddr1: Addr::from((i + 1, mode1)),
-                opAddr2: Addr::from((i + 2, mode2)),
-                destAddr: Pos(i + 3),
-            },
-            2 => Mult {
-                opAddr1: Addr::from((i + 1, mode1)),
-                opAddr2: Addr::from((i + 2, mode2)),
-                destAddr: Pos(i + 3),
-            },
-            3 => Read {
-                destAddr: Pos(i + 1),
-            },
-            4 => Write {
-                opAddr1: Addr::from((i + 1, mode1)),
-            },
-            5 => JumpIf {
-                boolAddr: Addr::from((i + 1, mode1)),
-                jumpAddr: Addr::from((i + 2, mode2)),
-            },
-            6 => JumpIfNot {
-                boolAddr: Addr::from((i + 1, mode1)),
-                jumpAddr: Addr::from((i + 2, mode2)),
-            },
-            7 => SetIfLt {
-                opAddr1: Addr::from((i + 1, mode1)),
-                opAddr2: Addr::from((i + 2, mode2)),
-                destAddr: Pos(i + 3),
-            },
-            8 => SetIfEq {
-                opAddr1: Addr::from((i + 1, mode1)),
-                opAddr2: Addr::from((i + 2, mode2)),
-                destAddr: Pos(i + 3),
-            },
-            99 => Halt,
-            _ => panic!(format!("{} is an invalid opcode. must be in [0,8]", opcode)),
+    #[test]
+    fn day05_basic4() {
+        for input_i in 6..=11 {
+            let data = vec![3, 3, 1108, -1, 8, 3, 4, 3, 99];
+            let input = vec![input_i];
+            assert_eq!(
+                vec![if input_i == 8 { 1 } else { 0 }],
+                run_program(input, data).unwrap()
+            );
         }
     }
-}
-impl OpCode {
-    fn numFields(&self) -> i32 {
-        match self {
-            Add {
-                opAddr1: _,
-                opAddr2: _,
-                destAddr: _,
-            } => 3,
-            Mult {
-                opAddr1: _,
-                opAddr2: _,
-                destAddr: _,
-            } => 3,
-            Read { destAddr: _ } => 1,
-            Write { opAddr1: _ } => 1,
-            JumpIf {
-                boolAddr: _,
-                jumpAddr: _,
-            } => 2,
-            JumpIfNot {
-                boolAddr: _,
-                jumpAddr: _,
-            } => 2,
-            SetIfLt {
-                opAddr1: _,
-                opAddr2: _,
-                destAddr: _,
-            } => 3,
-            SetIfEq {
-                opAddr1: _,
-                opAddr2: _,
-                destAddr: _,
-            } => 3,
-            Halt => 0,
+
+    #[test]
+    fn day05_basic5() {
+        for input_i in 6..=10 {
+            let data = vec![3, 3, 1107, -1, 8, 3, 4, 3, 99];
+            let input = vec![input_i];
+            assert_eq!(
+                vec![if input_i < 8 { 1 } else { 0 }],
+                run_program(input, data).unwrap()
+            );
         }
     }
-    pub fn executeIntruction(
-        &self,
-        data: &mut Vec<i32>,
-        i: &mut i32,
-        input: &mut dyn Iterator<Item = i32>,
-    ) -> Option<i32> {
-        let mut jumped = false;
-        let retval = match self {
-            Add {
-                opAddr1,
-                opAddr2,
-                destAddr,
-            } => {
-                *destAddr.getData(data) = *opAddr1.getData(data) + *opAddr2.getData(data);
-                None
-            }
-            Mult {
-                opAddr1,
-                opAddr2,
-                destAddr,
-            } => {
-                *destAddr.getData(data) = *opAddr1.getData(data) * *opAddr2.getData(data);
-                None
-            }
-            Read { destAddr } => {
-                *destAddr.getData(data) = input.next().unwrap();
-                None
-            }
-            Write { opAddr1 } => Some(*opAddr1.getData(data)),
-            JumpIf { boolAddr, jumpAddr } => {
-                if *boolAddr.getData(data) != 0 {
-                    *i = *jumpAddr.getData(data);
-                    jumped = true;
-                }
-                None
-            }
-            JumpIfNot { boolAddr, jumpAddr } => {
-                if *boolAddr.getData(data) == 0 {
-                    *i = *jumpAddr.getData(data);
-                    jumped = true;
-                }
-                None
-            }
-            SetIfLt {
-                opAddr1,
-                opAddr2,
-                destAddr,
-            } => {
-                *destAddr.getData(data) = if *opAddr1.getData(data) < *opAddr2.getData(data) {
-                    1
-                } else {
-                    0
-                };
-                None
-            }
-            SetIfEq {
-                opAddr1,
-                opAddr2,
-                destAddr,
-            } => {
-                *destAddr.getData(data) = if *opAddr1.getData(data) == *opAddr2.getData(data) {
-                    1
-                } else {
-                    0
-                };
-                None
-            }
-            Halt => {
-                *i = -1;
-                jumped = true;
-                None
-            }
-        };
-        if !jumped {
-            *i += self.numFields() + 1;
+
+    #[test]
+    fn day05_basic6() {
+        for input_i in 0..=1 {
+            let data = vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+            let input = vec![input_i];
+            assert_eq!(
+                vec![if input_i == 0 { 0 } else { 1 }],
+                run_program(input, data).unwrap()
+            );
         }
-        retval
+    }
+
+    #[test]
+    fn day05_basic7() {
+        for input_i in 0..=1 {
+            let data = vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
+            let input = vec![input_i];
+            assert_eq!(
+                vec![if input_i == 0 { 0 } else { 1 }],
+                run_program(input, data).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn day05_basic8() {
+        for input_i in 6..=10 {
+            let data = vec![
+                3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36,
+                98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000,
+                1, 20, 4, 20, 1105, 1, 46, 98, 99,
+            ];
+            let input = vec![input_i];
+            assert_eq!(
+                vec![match input_i.cmp(&8) {
+                    Ordering::Less => 999,
+                    Ordering::Equal => 1000,
+                    Ordering::Greater => 1001,
+                }],
+                run_program(input, data).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn day05() {
+        let data = vec![
+            3, 225, 1, 225, 6, 6, 1100, 1, 238, 225, 104, 0, 1102, 40, 93, 224, 1001, 224, -3720,
+            224, 4, 224, 102, 8, 223, 223, 101, 3, 224, 224, 1, 224, 223, 223, 1101, 56, 23, 225,
+            1102, 64, 78, 225, 1102, 14, 11, 225, 1101, 84, 27, 225, 1101, 7, 82, 224, 1001, 224,
+            -89, 224, 4, 224, 1002, 223, 8, 223, 1001, 224, 1, 224, 1, 224, 223, 223, 1, 35, 47,
+            224, 1001, 224, -140, 224, 4, 224, 1002, 223, 8, 223, 101, 5, 224, 224, 1, 224, 223,
+            223, 1101, 75, 90, 225, 101, 9, 122, 224, 101, -72, 224, 224, 4, 224, 1002, 223, 8,
+            223, 101, 6, 224, 224, 1, 224, 223, 223, 1102, 36, 63, 225, 1002, 192, 29, 224, 1001,
+            224, -1218, 224, 4, 224, 1002, 223, 8, 223, 1001, 224, 7, 224, 1, 223, 224, 223, 102,
+            31, 218, 224, 101, -2046, 224, 224, 4, 224, 102, 8, 223, 223, 101, 4, 224, 224, 1, 224,
+            223, 223, 1001, 43, 38, 224, 101, -52, 224, 224, 4, 224, 1002, 223, 8, 223, 101, 5,
+            224, 224, 1, 223, 224, 223, 1102, 33, 42, 225, 2, 95, 40, 224, 101, -5850, 224, 224, 4,
+            224, 1002, 223, 8, 223, 1001, 224, 7, 224, 1, 224, 223, 223, 1102, 37, 66, 225, 4, 223,
+            99, 0, 0, 0, 677, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1105, 0, 99999, 1105, 227, 247,
+            1105, 1, 99999, 1005, 227, 99999, 1005, 0, 256, 1105, 1, 99999, 1106, 227, 99999, 1106,
+            0, 265, 1105, 1, 99999, 1006, 0, 99999, 1006, 227, 274, 1105, 1, 99999, 1105, 1, 280,
+            1105, 1, 99999, 1, 225, 225, 225, 1101, 294, 0, 0, 105, 1, 0, 1105, 1, 99999, 1106, 0,
+            300, 1105, 1, 99999, 1, 225, 225, 225, 1101, 314, 0, 0, 106, 0, 0, 1105, 1, 99999,
+            1007, 226, 677, 224, 1002, 223, 2, 223, 1005, 224, 329, 1001, 223, 1, 223, 1007, 226,
+            226, 224, 1002, 223, 2, 223, 1006, 224, 344, 101, 1, 223, 223, 1107, 677, 226, 224,
+            102, 2, 223, 223, 1006, 224, 359, 1001, 223, 1, 223, 108, 677, 677, 224, 1002, 223, 2,
+            223, 1006, 224, 374, 1001, 223, 1, 223, 107, 677, 677, 224, 1002, 223, 2, 223, 1005,
+            224, 389, 101, 1, 223, 223, 8, 677, 677, 224, 1002, 223, 2, 223, 1005, 224, 404, 1001,
+            223, 1, 223, 108, 226, 226, 224, 1002, 223, 2, 223, 1005, 224, 419, 101, 1, 223, 223,
+            1008, 677, 677, 224, 1002, 223, 2, 223, 1005, 224, 434, 101, 1, 223, 223, 1008, 226,
+            226, 224, 1002, 223, 2, 223, 1005, 224, 449, 101, 1, 223, 223, 7, 677, 226, 224, 1002,
+            223, 2, 223, 1006, 224, 464, 1001, 223, 1, 223, 7, 226, 226, 224, 1002, 223, 2, 223,
+            1005, 224, 479, 1001, 223, 1, 223, 1007, 677, 677, 224, 102, 2, 223, 223, 1005, 224,
+            494, 101, 1, 223, 223, 1108, 677, 226, 224, 102, 2, 223, 223, 1006, 224, 509, 1001,
+            223, 1, 223, 8, 677, 226, 224, 102, 2, 223, 223, 1005, 224, 524, 1001, 223, 1, 223,
+            1107, 226, 226, 224, 102, 2, 223, 223, 1006, 224, 539, 1001, 223, 1, 223, 1008, 226,
+            677, 224, 1002, 223, 2, 223, 1006, 224, 554, 1001, 223, 1, 223, 1107, 226, 677, 224,
+            1002, 223, 2, 223, 1006, 224, 569, 1001, 223, 1, 223, 1108, 677, 677, 224, 102, 2, 223,
+            223, 1005, 224, 584, 101, 1, 223, 223, 7, 226, 677, 224, 102, 2, 223, 223, 1006, 224,
+            599, 1001, 223, 1, 223, 1108, 226, 677, 224, 102, 2, 223, 223, 1006, 224, 614, 101, 1,
+            223, 223, 107, 226, 677, 224, 1002, 223, 2, 223, 1005, 224, 629, 101, 1, 223, 223, 108,
+            226, 677, 224, 1002, 223, 2, 223, 1005, 224, 644, 101, 1, 223, 223, 8, 226, 677, 224,
+            1002, 223, 2, 223, 1005, 224, 659, 1001, 223, 1, 223, 107, 226, 226, 224, 1002, 223, 2,
+            223, 1006, 224, 674, 101, 1, 223, 223, 4, 223, 99, 226,
+        ];
+        let input = vec![5];
+        assert_eq!(vec![9168267], run_program(input, data).unwrap())
     }
 }
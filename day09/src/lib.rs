@@ -1,115 +1,92 @@
-mod opcode;
-use opcode::*;
-
-#[cfg(test)]
-use colored::*;
-
-pub fn run_program(input: Vec<i64>, mut data: Vec<i64>) -> Vec<i64> {
-    let mut relative_base = 0;
-    let mut program_counter = 0;
-    let mut output = Vec::new();
-    let mut input_iter = input.into_iter();
-
-    while (program_counter as usize) < data.len() {
-        let opcode = OpCode::from(CurrentState {
-            data: &mut data,
-            program_counter: program_counter,
-            relative_base: relative_base,
-        });
-        #[cfg(test)]
-        {
-            println!("data {}", nums_to_string(&data, Some(program_counter)));
-            println!("code {:?}", opcode);
-        }
-        if let Some(o) = opcode.executeIntruction(
-            &mut relative_base,
-            &mut data,
-            &mut program_counter,
-            &mut input_iter,
-        ) {
-            output.push(o);
-        }
-        #[cfg(test)]
-        {
-            println!("out  {}", nums_to_string(&output, None));
-        }
-    }
-
-    output
-}
-
-#[cfg(test)]
-fn nums_to_string(nums: &Vec<i64>, color_i: Option<i64>) -> String {
-    let ss: Vec<String> = nums
-        .iter()
-        .enumerate()
-        .map(|(i, n)| match color_i {
-            Some(color_i) if color_i as usize == i => n.to_string().blue().to_string(),
-            _ => n.to_string(),
-        })
-        .collect();
-    ss.join(",")
-}
+// The BOOST program, run on the shared intcode machine.
+pub use intcode::*;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    // plenty for any of these programs, so a broken VM fails a test instead of hanging it
+    const LIMITS: Limits = Limits {
+        fuel: Some(10_000_000),
+        time: None,
+    };
     #[test]
     fn test_1() {
         let data = vec![
             109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
         ];
-        let output = run_program(vec![], data.clone());
+        let output = run_program_with_limits(vec![], data.clone(), LIMITS).unwrap();
         assert_eq!(output, data);
     }
     #[test]
     fn test_2() {
-        let output = run_program(vec![], vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0]);
-        assert_eq!((*output.get(0).unwrap() as f64).log10().ceil() as i64, 16);
+        let output = run_program_with_limits(
+            vec![],
+            vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0],
+            LIMITS,
+        )
+        .unwrap();
+        assert_eq!((*output.first().unwrap() as f64).log10().ceil() as i64, 16);
     }
     #[test]
     fn test_3() {
         let data = vec![104, 1125899906842624, 99];
-        let output = run_program(vec![], data.clone());
-        assert_eq!(data.get(1).unwrap(), output.get(0).unwrap());
+        let output = run_program_with_limits(vec![], data.clone(), LIMITS).unwrap();
+        assert_eq!(data.get(1).unwrap(), output.first().unwrap());
     }
     #[test]
     fn test_4() {
         let data = vec![1101, 1, 1, 7, 4, 7, 99, 0];
-        let output = run_program(vec![], data.clone());
-        assert_eq!(*output.get(0).unwrap(), 2);
+        let output = run_program_with_limits(vec![], data.clone(), LIMITS).unwrap();
+        assert_eq!(*output.first().unwrap(), 2);
     }
     #[test]
     fn test_5() {
         let data = vec![203, 5, 4, 5, 99, 0];
         let input_number = 42;
-        let output = run_program(vec![input_number], data.clone());
-        assert_eq!(*output.get(0).unwrap(), input_number);
+        let output = run_program_with_limits(vec![input_number], data.clone(), LIMITS).unwrap();
+        assert_eq!(*output.first().unwrap(), input_number);
     }
     #[test]
     fn part_1() {
-        let data: Vec<i64> = std::fs::read_to_string("data/input.txt")
-            .unwrap()
-            .lines()
-            .next()
-            .unwrap()
-            .split(",")
-            .map(|s| s.parse().unwrap())
-            .collect();
-        let output = run_program(vec![1], data);
-        assert_eq!(*output.get(0).unwrap(), 3507134798);
+        let data: Vec<i64> = Program::load("data/input.txt").unwrap().into();
+        let output = run_program_with_limits(vec![1], data, LIMITS).unwrap();
+        assert_eq!(*output.first().unwrap(), 3507134798);
     }
     #[test]
     fn part_2() {
-        let data: Vec<i64> = std::fs::read_to_string("data/input.txt")
-            .unwrap()
-            .lines()
-            .next()
-            .unwrap()
-            .split(",")
-            .map(|s| s.parse().unwrap())
-            .collect();
-        let output = run_program(vec![2], data);
-        assert_eq!(*output.get(0).unwrap(), 84513);
+        let data: Vec<i64> = Program::load("data/input.txt").unwrap().into();
+        let output = run_program_with_limits(vec![2], data, LIMITS).unwrap();
+        assert_eq!(*output.first().unwrap(), 84513);
+    }
+    #[test]
+    fn part_2_from_snapshot() {
+        let data: Vec<i64> = Program::load("data/input.txt").unwrap().into();
+        let mut machine = Machine::new(data);
+        machine.push_input(2);
+        for _ in 0..100_000 {
+            assert_eq!(machine.step().unwrap(), None);
+        }
+
+        let bytes = machine.snapshot(&[]).to_bytes();
+        let mut restored: Machine = Snapshot::from_bytes(&bytes).unwrap().restore().unwrap();
+        assert_eq!(restored.steps(), 100_000);
+        assert_eq!(restored.run_until().unwrap(), Status::Output(84513));
+        assert_eq!(machine.run_until().unwrap(), Status::Output(84513));
+    }
+    #[test]
+    fn part_1_memory_diff() {
+        let data: Vec<i64> = Program::load("data/input.txt").unwrap().into();
+        let mut machine = Machine::new(data.clone());
+        machine.push_input(1);
+        assert_eq!(machine.run_until().unwrap(), Status::Output(3507134798));
+
+        // the answer is kept in a variable after the code, and the stack lives from 1000 up
+        let diff = diff_memory(&data, machine.data());
+        let changes = diff.changes();
+        assert_eq!(changes[0].start, 64);
+        assert_eq!(changes[0].after, vec![3507134798]);
+        assert!(changes[1..].iter().all(|c| c.start >= 1000));
+        assert_eq!(diff.count(Region::Code), 0);
     }
 }
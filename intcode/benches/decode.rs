@@ -0,0 +1,73 @@
+// Puzzle programs run without the decode cache, with a new cache for every run, and with one
+// cache shared by all the runs, the way a brute-force search would.
+//
+//     cargo bench --bench decode
+use intcode::{DecodeCache, Machine, Program, Status};
+use std::fs;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 100;
+
+// (name, program file relative to this crate, inputs for each run of the program)
+const PROGRAMS: [(&str, &str, &[&[i64]]); 4] = [
+    ("day05", "../day05/input.txt", &[&[5]]),
+    (
+        "day07",
+        "../day07/data/input.txt",
+        &[&[5, 0], &[6, 0], &[7, 0], &[8, 0], &[9, 0]],
+    ),
+    ("day09 part 1", "../day09/data/input.txt", &[&[1]]),
+    ("day09 part 2", "../day09/data/input.txt", &[&[2]]),
+];
+
+#[derive(Clone, Copy)]
+enum Caching {
+    Off,
+    PerRun,
+    Shared,
+}
+
+fn run(program: &[i64], inputs: &[i64], cache: Option<DecodeCache>) -> Option<DecodeCache> {
+    let mut machine = Machine::new(program.to_vec());
+    machine.set_decode_cache(cache);
+    inputs.iter().for_each(|&i| machine.push_input(i));
+    while let Status::Output(_) = machine.run_until().unwrap() {}
+    machine.take_decode_cache()
+}
+
+fn time(program: &[i64], inputs: &[&[i64]], caching: Caching) -> Duration {
+    let start = Instant::now();
+    let mut shared = Some(DecodeCache::new());
+    for _ in 0..RUNS {
+        for inputs in inputs {
+            match caching {
+                Caching::Off => run(program, inputs, None),
+                Caching::PerRun => run(program, inputs, Some(DecodeCache::new())),
+                Caching::Shared => {
+                    shared = run(program, inputs, shared.take());
+                    None
+                }
+            };
+        }
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    println!(
+        "{:<14} {:>14} {:>14} {:>14}",
+        "program", "uncached", "cache per run", "shared cache"
+    );
+    for (name, path, inputs) in PROGRAMS.iter() {
+        // day05's file has its input on the lines after the program
+        let text = fs::read_to_string(path).unwrap();
+        let program: Vec<i64> = Program::parse(text.lines().next().unwrap()).unwrap().into();
+        println!(
+            "{:<14} {:>14?} {:>14?} {:>14?}",
+            name,
+            time(&program, inputs, Caching::Off),
+            time(&program, inputs, Caching::PerRun),
+            time(&program, inputs, Caching::Shared),
+        );
+    }
+}
@@ -0,0 +1,40 @@
+// Forking a machine with a big memory image, then running it until its first output, which
+// reads from the middle and the end of the image but writes to a single page. Dense memory
+// copies the whole image on every fork, while paged memory copies just the page written to,
+// however big the image is and however much of it is read.
+//
+//     cargo bench --bench fork
+use intcode::{Machine, Memory, Status};
+use std::time::{Duration, Instant};
+
+const FORKS: u32 = 100;
+
+// read a number and output it doubled, times the 1s in the middle and at the end, forever,
+// followed by `size` words of data
+fn program(size: usize) -> Vec<i64> {
+    let (middle, end) = (size as i64 / 2, size as i64 - 1);
+    let mut data = vec![
+        3, 100, 1002, 100, 2, 100, 2, 100, middle, 100, 2, 100, end, 100, 4, 100, 1105, 1, 0,
+    ];
+    data.resize(size, 1);
+    data
+}
+
+fn time_forks<M: Memory + Clone>(machine: &Machine<M>) -> Duration {
+    let start = Instant::now();
+    for i in 0..FORKS {
+        let mut fork = machine.fork();
+        fork.push_input(i as i64);
+        assert_eq!(fork.run_until().unwrap(), Status::Output(2 * i as i64));
+    }
+    start.elapsed() / FORKS
+}
+
+fn main() {
+    println!("{:>10} {:>14} {:>14}", "words", "dense fork", "paged fork");
+    for &size in &[1 << 12, 1 << 16, 1 << 20, 1 << 24] {
+        let dense = time_forks(&Machine::new(program(size)));
+        let paged = time_forks(&Machine::paged(program(size)));
+        println!("{:>10} {:>14?} {:>14?}", size, dense, paged);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::opcode::Mode;
+use crate::InstructionSet;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+// Assembles the same syntax `disassemble` prints:
+//
+//     loop:             ; labels end in ':' and may share a line with an instruction
+//         IN -> [count]
+//         ADD #5, rb-1 -> [3]
+//         JNZ #1, loop  ; a bare label is the immediate address of that label
+//     count: .data 0
+//
+// Operands are `#imm`, `[pos]` or `rb+n`/`rb-n`, where `imm` and `pos` may also be labels.
+// A leading `12:` address, as in a listing, is ignored.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+impl Error for AsmError {}
+
+enum Value {
+    Number(i64),
+    Label(String),
+}
+
+struct Operand {
+    mode: Mode,
+    value: Value,
+}
+
+enum Item {
+    Instruction { opcode: i64, operands: Vec<Operand> },
+    Data(Vec<Value>),
+}
+
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    assemble_with(source, InstructionSet::shared_standard())
+}
+
+// Assemble for a machine decoding with `instructions`, so custom mnemonics can be used.
+pub fn assemble_with(source: &str, instructions: &InstructionSet) -> Result<Vec<i64>, AsmError> {
+    let mut labels: HashMap<String, i64> = HashMap::new();
+    let mut items: Vec<(usize, Item)> = Vec::new();
+    let mut address = 0;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let error = |message: String| AsmError {
+            line: line_number,
+            message,
+        };
+
+        let mut rest = line.split(';').next().unwrap().trim();
+        while let Some(colon) = rest.find(':') {
+            let name = rest[..colon].trim();
+            if name.parse::<i64>().is_err() {
+                if !is_label(name) {
+                    return Err(error(format!("'{}' is not a valid label", name)));
+                }
+                if labels.insert(name.to_string(), address).is_some() {
+                    return Err(error(format!("label '{}' is already defined", name)));
+                }
+            }
+            rest = rest[colon + 1..].trim();
+        }
+        if rest.is_empty() {
+            continue;
+        }
+
+        let (word, args) = match rest.find(char::is_whitespace) {
+            Some(space) => (&rest[..space], rest[space..].trim()),
+            None => (rest, ""),
+        };
+        let item = if word == ".data" {
+            let values = split_args(args)
+                .into_iter()
+                .map(value)
+                .collect::<Result<Vec<Value>, String>>()
+                .map_err(error)?;
+            if values.is_empty() {
+                return Err(error(String::from(".data needs at least one value")));
+            }
+            Item::Data(values)
+        } else {
+            instruction(word, args, instructions).map_err(error)?
+        };
+
+        address += match &item {
+            Item::Instruction { operands, .. } => operands.len() as i64 + 1,
+            Item::Data(values) => values.len() as i64,
+        };
+        items.push((line_number, item));
+    }
+
+    let mut program = Vec::new();
+    for (line_number, item) in items {
+        let resolve = |value: &Value| match value {
+            Value::Number(n) => Ok(*n),
+            Value::Label(name) => labels.get(name).cloned().ok_or_else(|| AsmError {
+                line: line_number,
+                message: format!("label '{}' is not defined", name),
+            }),
+        };
+        match item {
+            Item::Instruction { opcode, operands } => {
+                let modes = operands
+                    .iter()
+                    .enumerate()
+                    .map(|(i, operand)| i64::from(&operand.mode) * 10i64.pow(i as u32 + 2))
+                    .sum::<i64>();
+                program.push(opcode + modes);
+                for operand in &operands {
+                    program.push(resolve(&operand.value)?);
+                }
+            }
+            Item::Data(values) => {
+                for value in &values {
+                    program.push(resolve(value)?);
+                }
+            }
+        }
+    }
+    Ok(program)
+}
+
+fn instruction(word: &str, args: &str, instructions: &InstructionSet) -> Result<Item, String> {
+    let instruction = instructions
+        .find(word)
+        .ok_or_else(|| format!("unknown mnemonic '{}'", word))?;
+    let (mnemonic, opcode) = (instruction.mnemonic, instruction.code);
+    let (num_sources, has_dest) = (instruction.sources, instruction.writes);
+
+    let (sources, dest) = match args.find("->") {
+        Some(arrow) => (&args[..arrow], Some(args[arrow + 2..].trim())),
+        None => (args, None),
+    };
+    let sources = split_args(sources);
+    if sources.len() != num_sources {
+        return Err(format!(
+            "{} takes {} source operand(s), found {}",
+            mnemonic,
+            num_sources,
+            sources.len()
+        ));
+    }
+    let mut operands = sources
+        .into_iter()
+        .map(operand)
+        .collect::<Result<Vec<Operand>, String>>()?;
+
+    match (has_dest, dest) {
+        (true, Some(dest)) => {
+            let dest = operand(dest)?;
+            if let Mode::ImmediateMode = dest.mode {
+                return Err(format!("{} cannot write to an immediate operand", mnemonic));
+            }
+            operands.push(dest);
+        }
+        (true, None) => return Err(format!("{} needs a '-> dest' operand", mnemonic)),
+        (false, Some(_)) => return Err(format!("{} has no destination operand", mnemonic)),
+        (false, None) => (),
+    }
+    Ok(Item::Instruction { opcode, operands })
+}
+
+fn split_args(args: &str) -> Vec<&str> {
+    if args.trim().is_empty() {
+        return vec![];
+    }
+    args.split(',').map(|arg| arg.trim()).collect()
+}
+
+fn operand(s: &str) -> Result<Operand, String> {
+    if let Some(imm) = s.strip_prefix('#') {
+        Ok(Operand {
+            mode: Mode::ImmediateMode,
+            value: value(imm.trim())?,
+        })
+    } else if s.starts_with('[') && s.ends_with(']') {
+        Ok(Operand {
+            mode: Mode::PositionMode,
+            value: value(s[1..s.len() - 1].trim())?,
+        })
+    } else if s.starts_with("rb+") || s.starts_with("rb-") {
+        let offset = s[2..].replace(' ', "");
+        let offset = offset.strip_prefix('+').unwrap_or(&offset);
+        match offset.parse() {
+            Ok(n) => Ok(Operand {
+                mode: Mode::RelativeMode,
+                value: Value::Number(n),
+            }),
+            Err(_) => Err(format!("'{}' is not a valid relative operand", s)),
+        }
+    } else if is_label(s) {
+        Ok(Operand {
+            mode: Mode::ImmediateMode,
+            value: Value::Label(s.to_string()),
+        })
+    } else {
+        Err(format!(
+            "'{}' is not a valid operand, expected #imm, [pos] or rb+n",
+            s
+        ))
+    }
+}
+
+fn value(s: &str) -> Result<Value, String> {
+    if let Ok(n) = s.parse() {
+        Ok(Value::Number(n))
+    } else if is_label(s) {
+        Ok(Value::Label(s.to_string()))
+    } else {
+        Err(format!("'{}' is not a number or label", s))
+    }
+}
+
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            s != "rb" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitwise, disassemble, run_program, Machine, Status};
+
+    #[test]
+    fn encodes_modes() {
+        let program = assemble("ADD #1, [2] -> rb-3\nHLT").unwrap();
+        assert_eq!(program, vec![20101, 1, 2, -3, 99]);
+    }
+
+    #[test]
+    fn labels_and_data() {
+        let source = "
+            ; print the numbers in `list` until the zero at its end
+            start:  ARB #list
+            loop:   JZ rb+0, end
+                    OUT rb+0
+                    ARB #1
+                    JNZ #1, loop
+            end:    HLT
+            list:   .data 3, 2, 1
+                    .data 0
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(program[1], 13);
+        assert_eq!(run_program(vec![], program).unwrap(), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let cases = [
+            ("HLT\nFOO #1", 2, "unknown mnemonic 'FOO'"),
+            ("ADD #1 -> [0]", 1, "ADD takes 2 source operand(s), found 1"),
+            ("\n\nIN -> #3", 3, "IN cannot write to an immediate operand"),
+            ("OUT [x]", 1, "label 'x' is not defined"),
+            ("a: HLT\na: HLT", 2, "label 'a' is already defined"),
+            (
+                "OUT %1",
+                1,
+                "'%1' is not a valid operand, expected #imm, [pos] or rb+n",
+            ),
+        ];
+        for (source, line, message) in cases.iter() {
+            let error = assemble(source).unwrap_err();
+            assert_eq!(error.line, *line);
+            assert_eq!(error.message, *message);
+        }
+    }
+
+    #[test]
+    fn custom_instructions() {
+        let source = "AND #12, #10 -> [x]\nOUT [x]\nHLT\nx: .data 0";
+        assert_eq!(
+            assemble(source).unwrap_err().message,
+            "unknown mnemonic 'AND'"
+        );
+        let set = InstructionSet::standard().with(bitwise()).unwrap();
+        let program = assemble_with(source, &set).unwrap();
+        assert_eq!(program, vec![1120, 12, 10, 7, 4, 7, 99, 0]);
+
+        let mut machine = Machine::new(program);
+        machine.set_instructions(set);
+        assert_eq!(machine.run_until(), Ok(Status::Output(8)));
+    }
+
+    #[test]
+    fn reassembles_a_listing() {
+        let program = vec![3, 13, 1006, 13, 12, 2101, 5, -1, 3, 1105, 1, 0, 99, 7, 7];
+        let listing = disassemble(&program).to_string();
+        assert_eq!(assemble(&listing).unwrap(), program);
+    }
+}
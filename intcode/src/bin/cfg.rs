@@ -0,0 +1,20 @@
+use intcode::{control_flow_graph, input_arg, Program};
+use std::process;
+
+const USAGE: &str = "usage: cfg --input <program file>";
+
+// Prints the program's control-flow graph for Graphviz, e.g. `cfg --input prog.txt | dot -Tsvg`.
+fn main() {
+    let path = input_arg().unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        process::exit(2)
+    });
+    let data: Vec<i64> = Program::load(&path)
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1)
+        })
+        .into();
+
+    print!("{}", control_flow_graph(&data).to_dot());
+}
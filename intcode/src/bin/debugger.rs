@@ -0,0 +1,49 @@
+use intcode::{input_arg, limits_arg, overflow_arg, Debugger, Machine, Program, HELP};
+use std::io::{self, BufRead, Write};
+use std::process;
+
+const USAGE: &str =
+    "usage: debugger --input <program file> [--fuel <n>] [--time-limit <s>] [--overflow <policy>]";
+
+fn main() {
+    let path = input_arg().unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        process::exit(2)
+    });
+    let data: Vec<i64> = Program::load(&path)
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1)
+        })
+        .into();
+
+    let mut machine = Machine::new(data);
+    match (limits_arg(), overflow_arg()) {
+        (Ok(limits), Ok(overflow)) => {
+            machine.set_limits(limits);
+            machine.set_overflow(overflow);
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2)
+        }
+    }
+    let mut debugger = Debugger::new(machine);
+    println!("{}\nquit              exit the debugger\n", HELP);
+    println!("{}", debugger.registers());
+
+    let stdin = io::stdin();
+    loop {
+        print!("(icdb) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        match line.trim() {
+            "q" | "quit" => break,
+            "" => continue,
+            command => println!("{}", debugger.command(command)),
+        }
+    }
+}
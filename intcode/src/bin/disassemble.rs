@@ -0,0 +1,19 @@
+use intcode::{disassemble, input_arg, Program};
+use std::process;
+
+const USAGE: &str = "usage: disassemble --input <program file>";
+
+fn main() {
+    let path = input_arg().unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        process::exit(2)
+    });
+    let data: Vec<i64> = Program::load(&path)
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1)
+        })
+        .into();
+
+    print!("{}", disassemble(&data));
+}
@@ -0,0 +1,40 @@
+use intcode::{fuzz, generate, positional_args, Rng};
+use std::process;
+
+const SIZE: usize = 64;
+const USAGE: &str = "usage: fuzz [runs] [first seed]";
+
+// Differential fuzzing of the machine, e.g. `fuzz 100000` to check that many random programs,
+// or `fuzz 1 <seed>` to show the program a failing seed generates.
+fn main() {
+    let args: Vec<u64> = positional_args()
+        .iter()
+        .map(|arg| arg.parse())
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|_| {
+            eprintln!("{}", USAGE);
+            process::exit(2)
+        });
+    let runs = args.first().cloned().unwrap_or(10_000);
+    let seed = args.get(1).cloned().unwrap_or(0);
+
+    match fuzz(seed, runs, SIZE) {
+        Ok(skipped) => {
+            let unchecked: u64 = skipped.values().sum();
+            println!(
+                "{} programs ok, {} of them not checked symbolically",
+                runs, unchecked
+            );
+            for (why, count) in skipped {
+                println!("{:>10} {}", count, why);
+            }
+        }
+        Err((seed, why)) => {
+            let program = generate(&mut Rng::new(seed), SIZE);
+            println!("seed {} failed: {}", seed, why);
+            println!("program: {:?}", program.data);
+            println!("input: {:?}", program.input);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use intcode::{
+    ascii_line, ascii_output, flag, input_arg, limits_arg, overflow_arg, positional_args,
+    InputSource, Machine, OutputSink, Program,
+};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+const USAGE: &str =
+    "usage: intcode --input <program file> [--ascii] [--fuel <n>] [--time-limit <s>] [--overflow <policy>] [input...]";
+
+// Run a program, taking input from the command line or, without any there, from stdin as it's
+// needed. With --ascii every input is a line of text and outputs are printed as characters.
+fn main() {
+    let ascii = flag("--ascii");
+    let path = input_arg().unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        process::exit(2)
+    });
+    let data: Vec<i64> = Program::load(&path)
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1)
+        })
+        .into();
+
+    let args: Vec<String> = positional_args();
+    let mut input: Box<dyn InputSource> = if args.is_empty() {
+        Box::new(Stdin {
+            ascii,
+            pending: VecDeque::new(),
+        })
+    } else {
+        let mut pending = VecDeque::new();
+        for arg in args {
+            match (ascii, arg.parse()) {
+                (true, _) => pending.extend(ascii_line(&arg)),
+                (false, Ok(i)) => pending.push_back(i),
+                (false, Err(_)) => {
+                    eprintln!("input must be numbers without --ascii: {:?}", arg);
+                    process::exit(2)
+                }
+            }
+        }
+        Box::new(pending)
+    };
+
+    let mut machine = Machine::new(data);
+    match (limits_arg(), overflow_arg()) {
+        (Ok(limits), Ok(overflow)) => {
+            machine.set_limits(limits);
+            machine.set_overflow(overflow);
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2)
+        }
+    }
+    if let Err(e) = machine.run_io(&mut *input, &mut Stdout { ascii }) {
+        println!();
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+// Reads a line whenever the program wants input and has none left: numbers separated by commas
+// or spaces, or with --ascii a line of text.
+struct Stdin {
+    ascii: bool,
+    pending: VecDeque<i64>,
+}
+impl InputSource for Stdin {
+    fn recv(&mut self) -> Option<i64> {
+        while self.pending.is_empty() {
+            io::stdout().flush().ok();
+            let mut line = String::new();
+            if io::stdin().lock().read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            let line = line.trim_end_matches(&['\n', '\r'][..]);
+            if self.ascii {
+                self.pending.extend(ascii_line(line));
+                continue;
+            }
+            for word in line.split(|c: char| c == ',' || c.is_whitespace()) {
+                match word.parse() {
+                    Ok(i) => self.pending.push_back(i),
+                    Err(_) if word.is_empty() => (),
+                    Err(_) => eprintln!("ignoring {:?}, not a number", word),
+                }
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+struct Stdout {
+    ascii: bool,
+}
+impl OutputSink for Stdout {
+    fn send(&mut self, o: i64) {
+        if self.ascii {
+            print!("{}", ascii_output(o));
+        } else {
+            println!("{}", o);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use intcode::{input_arg, limits_arg, overflow_arg, positional_args, Machine, Program, Status};
+use std::process;
+
+const USAGE: &str =
+    "usage: profile --input <program file> [--fuel <n>] [--time-limit <s>] [--overflow <policy>] [input...]";
+
+// Run a program to completion and print where it spent its time. Any numbers on the command
+// line besides the options are its input.
+fn main() {
+    let path = input_arg().unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        process::exit(2)
+    });
+    let data: Vec<i64> = Program::load(&path)
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1)
+        })
+        .into();
+
+    let mut machine = Machine::new(data);
+    match (limits_arg(), overflow_arg()) {
+        (Ok(limits), Ok(overflow)) => {
+            machine.set_limits(limits);
+            machine.set_overflow(overflow);
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2)
+        }
+    }
+    for arg in positional_args() {
+        match arg.parse() {
+            Ok(i) => machine.push_input(i),
+            Err(_) => {
+                eprintln!("input must be numbers: {:?}\n{}", arg, USAGE);
+                process::exit(2)
+            }
+        }
+    }
+    machine.start_profile();
+    // a program that fails still gets its profile up to there
+    let failed = loop {
+        match machine.run_until() {
+            Ok(Status::Output(o)) => println!("output: {}", o),
+            Ok(Status::Halted) => break false,
+            Ok(Status::NeedsInput) => {
+                eprintln!("ran out of input at address {}", machine.program_counter());
+                break false;
+            }
+            Ok(Status::OutOfFuel { address, steps }) => {
+                eprintln!("out of fuel at address {} after {} steps", address, steps);
+                break false;
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                break true;
+            }
+        }
+    };
+    print!("\n{}", machine.profile().unwrap().report(20));
+    if failed {
+        process::exit(1)
+    }
+}
@@ -0,0 +1,302 @@
+use crate::disassemble::{copy_populated, exits, find_code, Code};
+use crate::opcode::*;
+use crate::{render_instruction, Memory, PagedMemory};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    FallThrough,
+    Jump,
+}
+
+// between the blocks starting at `from` and `to`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+// A run of instructions only ever entered at the first and left after the last.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    pub instructions: Vec<usize>,
+}
+
+// An instruction at `from` whose destination operand is part of the instruction at `to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeWrite {
+    pub from: usize,
+    pub to: usize,
+}
+
+// The control-flow graph of a program image, found statically from address 0 the way
+// `disassemble` finds code. Conditions and jump targets that are immediate are resolved, so a
+// jump that can never be taken (like day05's `JNZ #0, #99999` traps) has no edge; jumps to
+// wherever a cell says are only flagged.
+pub struct Cfg {
+    // just the populated part of the image
+    words: PagedMemory,
+    code: BTreeMap<usize, OpCode>,
+    blocks: BTreeMap<usize, Block>,
+    edges: Vec<Edge>,
+    indirect_jumps: Vec<usize>,
+    code_writes: Vec<CodeWrite>,
+}
+impl Cfg {
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    pub fn block(&self, start: usize) -> Option<&Block> {
+        self.blocks.get(&start)
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    // starts of the blocks control can go to from the block starting at `start`
+    pub fn successors(&self, start: usize) -> Vec<usize> {
+        let edges = self.edges.iter().filter(|edge| edge.from == start);
+        edges.map(|edge| edge.to).collect()
+    }
+
+    // addresses of jumps whose target isn't known until they run
+    pub fn indirect_jumps(&self) -> &[usize] {
+        &self.indirect_jumps
+    }
+
+    // Writes that will change code, as far as can be told without running it: only writes
+    // through a position operand are found. Where a relative operand writes depends on the
+    // relative base when it runs, so self-modification that way is not detected.
+    pub fn code_writes(&self) -> &[CodeWrite] {
+        &self.code_writes
+    }
+
+    // The graph in Graphviz format: blocks list their instructions, blocks ending in an indirect
+    // jump are red, and dotted red edges go from code writes to the code they change.
+    pub fn to_dot(&self) -> String {
+        let block_of = |address: usize| {
+            let instruction = instruction_at(&self.code, address)?;
+            let (start, block) = self.blocks.range(..=instruction).next_back()?;
+            block.instructions.contains(&instruction).then_some(*start)
+        };
+
+        let mut s = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for address in &block.instructions {
+                let instruction = render_instruction(&self.code[address], &self.words);
+                write!(label, "{}: {}\\l", address, instruction).unwrap();
+            }
+            let last = block.instructions.last().unwrap();
+            let color = if self.indirect_jumps.contains(last) {
+                ", color=red"
+            } else {
+                ""
+            };
+            writeln!(s, "    b{} [label=\"{}\"{}];", block.start, label, color).unwrap();
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::FallThrough => "",
+                EdgeKind::Jump => " [label=jump]",
+            };
+            writeln!(s, "    b{} -> b{}{};", edge.from, edge.to, style).unwrap();
+        }
+        for write in &self.code_writes {
+            if let (Some(from), Some(to)) = (block_of(write.from), block_of(write.to)) {
+                writeln!(
+                    s,
+                    "    b{} -> b{} [style=dotted, color=red, label=\"writes {}\"];",
+                    from, to, write.to
+                )
+                .unwrap();
+            }
+        }
+        s.push_str("}\n");
+        s
+    }
+}
+
+// the start of the instruction that `address` is part of
+fn instruction_at(code: &BTreeMap<usize, OpCode>, address: usize) -> Option<usize> {
+    let (start, opcode) = code.range(..=address).next_back()?;
+    (address <= start + opcode.numFields() as usize).then_some(*start)
+}
+
+pub fn control_flow_graph(data: &dyn Memory) -> Cfg {
+    let Code {
+        instructions: code,
+        targets,
+    } = find_code(data);
+
+    // blocks start at address 0, wherever a jump goes, and after anything that can jump
+    let mut leaders: BTreeSet<usize> = targets;
+    leaders.insert(0);
+    for (&address, opcode) in &code {
+        let exits = exits(opcode, address, data);
+        if exits.jump.is_some() || exits.indirect {
+            leaders.extend(exits.next);
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    let mut edges = Vec::new();
+    let mut indirect_jumps = Vec::new();
+    for &start in leaders.iter().filter(|a| code.contains_key(a)) {
+        let mut instructions = vec![start];
+        let mut address = start;
+        loop {
+            let exits = exits(&code[&address], address, data);
+            if exits.indirect {
+                indirect_jumps.push(address);
+            }
+            if let Some(target) = exits.jump.filter(|t| code.contains_key(t)) {
+                edges.push(Edge {
+                    from: start,
+                    to: target,
+                    kind: EdgeKind::Jump,
+                });
+            }
+            match exits.next.filter(|next| code.contains_key(next)) {
+                Some(next) if leaders.contains(&next) || exits.jump.is_some() || exits.indirect => {
+                    edges.push(Edge {
+                        from: start,
+                        to: next,
+                        kind: EdgeKind::FallThrough,
+                    });
+                    break;
+                }
+                Some(next) => {
+                    instructions.push(next);
+                    address = next;
+                }
+                None => break,
+            }
+        }
+        blocks.insert(
+            start,
+            Block {
+                start,
+                instructions,
+            },
+        );
+    }
+    edges.sort();
+    edges.dedup();
+
+    // writes through a position operand land where its cell says
+    let code_writes = code
+        .iter()
+        .filter_map(|(&from, opcode)| match opcode.dest() {
+            Some(Addr::Position(Pos(i))) => {
+                let target = usize::try_from(data.read(*i as usize)).ok()?;
+                instruction_at(&code, target).map(|_| CodeWrite { from, to: target })
+            }
+            _ => None,
+        })
+        .collect();
+
+    Cfg {
+        words: copy_populated(data),
+        code,
+        blocks,
+        edges,
+        indirect_jumps,
+        code_writes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble;
+
+    #[test]
+    fn loops_and_branches() {
+        let program = assemble(
+            "
+                    IN -> [n]
+            loop:   ADD [total], [n] -> [total]
+                    ADD [n], #-1 -> [n]
+                    JNZ [n], loop
+                    OUT [total]
+                    HLT
+            n:      .data 0
+            total:  .data 0
+            ",
+        )
+        .unwrap();
+        let cfg = control_flow_graph(&program);
+        let starts: Vec<usize> = cfg.blocks().map(|block| block.start).collect();
+        assert_eq!(starts, vec![0, 2, 13]);
+        assert_eq!(cfg.block(2).unwrap().instructions, vec![2, 6, 10]);
+        assert_eq!(cfg.successors(0), vec![2]);
+        assert_eq!(cfg.successors(2), vec![2, 13]);
+        assert_eq!(cfg.successors(13), Vec::<usize>::new());
+        assert!(cfg.indirect_jumps().is_empty());
+        assert!(cfg.code_writes().is_empty());
+
+        let dot = cfg.to_dot();
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("    b2 -> b2 [label=jump];\n"));
+        assert!(dot.contains("    b2 -> b13;\n"));
+        assert!(dot.contains("b13 [label=\"13: OUT [17]\\l15: HLT\\l\"];"));
+    }
+
+    #[test]
+    fn constant_conditions_and_indirect_jumps() {
+        // day05's diagnostics guard against wrong jumps with jumps that are never taken
+        let program = assemble(
+            "
+                    JNZ #0, #99999
+                    JZ #0, next
+                    OUT #1
+            next:   IN -> [target]
+                    JZ [flag], [target]
+                    HLT
+            flag:   .data 0
+            target: .data 0
+            ",
+        )
+        .unwrap();
+        let cfg = control_flow_graph(&program);
+        assert_eq!(cfg.successors(0), vec![8]);
+        assert!(cfg.block(6).is_none());
+        assert_eq!(cfg.block(0).unwrap().instructions, vec![0, 3]);
+        assert_eq!(cfg.indirect_jumps(), &[10]);
+        assert_eq!(cfg.successors(8), vec![13]);
+        assert!(cfg
+            .to_dot()
+            .contains("\"8: IN -> [15]\\l10: JZ [14], [15]\\l\", color=red];"));
+    }
+
+    #[test]
+    fn self_modifying_code() {
+        // turns its own output into a halt, and patches an operand the way day02 does
+        let program = assemble(
+            "
+                    ADD #99, #0 -> [patch]
+                    ADD #7, #0 -> [13]
+                    JNZ #1, patch
+                    HLT
+            patch:  OUT [0]
+                    HLT
+            ",
+        )
+        .unwrap();
+        let cfg = control_flow_graph(&program);
+        assert_eq!(
+            cfg.code_writes(),
+            &[CodeWrite { from: 0, to: 12 }, CodeWrite { from: 4, to: 13 }]
+        );
+        let dot = cfg.to_dot();
+        assert!(dot.contains("    b0 -> b12 [style=dotted, color=red, label=\"writes 12\"];\n"));
+        assert!(dot.contains("    b0 -> b12 [style=dotted, color=red, label=\"writes 13\"];\n"));
+    }
+}
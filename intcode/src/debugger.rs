@@ -0,0 +1,380 @@
+use crate::disassemble::copy_populated;
+use crate::{
+    diff_memory, render_instruction, Machine, Memory, MemoryDiff, PagedMemory, Status, VmError,
+};
+use colored::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+const DUMP_WORDS_PER_LINE: usize = 8;
+
+pub const HELP: &str = "\
+step [n]          execute n instructions (default 1)
+continue          run until a breakpoint, watchpoint, output, input or halt
+break <addr|OP>   stop before the instruction at addr, or before any OP (e.g. `break OUT`)
+watch <addr>      stop after the value at addr changes
+delete <addr|OP>  remove a breakpoint or watchpoint
+input <n> ...     queue input values
+regs              show the program counter, relative base and current instruction
+mem [addr] [len]  dump memory, highlighting the current instruction
+mark              remember memory as it is now, to diff against
+diff              list the cells changed since the mark (or the start)
+heatmap [width]   draw where memory changed since the mark (default 64 wide)
+output            show everything the program has output
+help              show this message";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint { address: i64 },
+    OpBreakpoint { address: i64 },
+    Watchpoint { address: usize, old: i64, new: i64 },
+    Status(Status),
+}
+
+pub struct Debugger<M: Memory = Vec<i64>> {
+    machine: Machine<M>,
+    breakpoints: BTreeSet<i64>,
+    op_breakpoints: BTreeSet<String>,
+    watchpoints: BTreeMap<usize, i64>,
+    output: Vec<i64>,
+    mark: PagedMemory,
+}
+impl<M: Memory> Debugger<M> {
+    pub fn new(machine: Machine<M>) -> Self {
+        let mark = copy_populated(machine.data());
+        Debugger {
+            mark,
+            machine,
+            breakpoints: BTreeSet::new(),
+            op_breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<M> {
+        &self.machine
+    }
+
+    pub fn output(&self) -> &Vec<i64> {
+        &self.output
+    }
+
+    pub fn push_input(&mut self, i: i64) {
+        self.machine.push_input(i);
+    }
+
+    pub fn add_breakpoint(&mut self, address: i64) {
+        self.breakpoints.insert(address);
+    }
+
+    // `mnemonic` as shown in a listing, e.g. "OUT"
+    pub fn add_op_breakpoint(&mut self, mnemonic: &str) {
+        self.op_breakpoints.insert(mnemonic.to_uppercase());
+    }
+
+    pub fn add_watchpoint(&mut self, address: usize) {
+        self.watchpoints
+            .insert(address, self.machine.data().read(address));
+    }
+
+    pub fn delete(&mut self, address: i64) -> bool {
+        let breakpoint = self.breakpoints.remove(&address);
+        let watchpoint = address >= 0 && self.watchpoints.remove(&(address as usize)).is_some();
+        breakpoint || watchpoint
+    }
+
+    pub fn delete_op_breakpoint(&mut self, mnemonic: &str) -> bool {
+        self.op_breakpoints.remove(&mnemonic.to_uppercase())
+    }
+
+    pub fn mark(&mut self) {
+        self.mark = copy_populated(self.machine.data());
+    }
+
+    // What changed in memory since the last `mark`, or since the debugger started.
+    pub fn diff(&self) -> MemoryDiff {
+        diff_memory(&self.mark, self.machine.data())
+    }
+
+    // Execute one instruction, ignoring breakpoints.
+    pub fn step(&mut self) -> Result<Option<Stop>, VmError> {
+        let status = self.machine.step()?;
+        if let Some(Status::Output(o)) = status {
+            self.output.push(o);
+        }
+
+        let data = self.machine.data();
+        for (&address, old) in self.watchpoints.iter_mut() {
+            let new = data.read(address);
+            if new != *old {
+                let stop = Stop::Watchpoint {
+                    address,
+                    old: *old,
+                    new,
+                };
+                *old = new;
+                return Ok(Some(stop));
+            }
+        }
+        Ok(status.map(Stop::Status))
+    }
+
+    // Run until something stops the machine. The instruction at the program counter always
+    // runs, so continuing from a breakpoint makes progress.
+    pub fn cont(&mut self) -> Result<Stop, VmError> {
+        if let Some(stop) = self.step()? {
+            return Ok(stop);
+        }
+        loop {
+            if let Some(stop) = self.breakpoint()? {
+                return Ok(stop);
+            }
+            if let Some(stop) = self.step()? {
+                return Ok(stop);
+            }
+        }
+    }
+
+    fn breakpoint(&self) -> Result<Option<Stop>, VmError> {
+        let address = self.machine.program_counter();
+        if self.breakpoints.contains(&address) {
+            return Ok(Some(Stop::Breakpoint { address }));
+        }
+        if !self.op_breakpoints.is_empty() && !self.machine.is_halted() {
+            let opcode = self.machine.current()?;
+            if self.op_breakpoints.contains(opcode.mnemonic()) {
+                return Ok(Some(Stop::OpBreakpoint { address }));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn registers(&self) -> String {
+        let instruction = if self.machine.is_halted() {
+            String::from("(halted)")
+        } else {
+            match self.machine.current() {
+                Ok(opcode) => render_instruction(&opcode, self.machine.data()),
+                Err(e) => format!("({})", e),
+            }
+        };
+        format!(
+            "pc {}  rb {}  {}",
+            self.machine.program_counter(),
+            self.machine.relative_base(),
+            instruction
+        )
+    }
+
+    // Memory from `start`, with the words of the current instruction highlighted.
+    pub fn dump(&self, start: usize, len: usize) -> String {
+        let pc = self.machine.program_counter();
+        let current = match self.machine.current() {
+            Ok(opcode) if pc >= 0 => pc as usize..pc as usize + opcode.numFields() as usize + 1,
+            _ => 0..0,
+        };
+        let data = self.machine.data();
+
+        let mut lines = Vec::new();
+        for line_start in (start..start + len).step_by(DUMP_WORDS_PER_LINE) {
+            let line_end = (line_start + DUMP_WORDS_PER_LINE).min(start + len);
+            let words: Vec<String> = (line_start..line_end)
+                .map(|a| match current.contains(&a) {
+                    true => data.read(a).to_string().blue().to_string(),
+                    false => data.read(a).to_string(),
+                })
+                .collect();
+            lines.push(format!("{:>6}: {}", line_start, words.join(",")));
+        }
+        lines.join("\n")
+    }
+
+    // Run one line of the REPL and return what to print.
+    pub fn command(&mut self, line: &str) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let args = words.iter().skip(1).map(|w| w.parse::<i64>());
+        let args: Result<Vec<i64>, _> = args.collect();
+
+        match (words.first().cloned().unwrap_or(""), args) {
+            ("s", Ok(args)) | ("step", Ok(args)) => {
+                let n = args.first().cloned().unwrap_or(1);
+                for _ in 0..n {
+                    match self.step() {
+                        Ok(Some(stop)) => return self.describe(Ok(stop)),
+                        Ok(None) => (),
+                        Err(e) => return self.describe(Err(e)),
+                    }
+                }
+                self.registers()
+            }
+            ("c", _) | ("continue", _) => {
+                let stop = self.cont();
+                self.describe(stop)
+            }
+            ("b", Ok(args)) | ("break", Ok(args)) if args.len() == 1 => {
+                self.add_breakpoint(args[0]);
+                format!("breakpoint at {}", args[0])
+            }
+            ("b", Err(_)) | ("break", Err(_)) if words.len() == 2 => {
+                self.add_op_breakpoint(words[1]);
+                format!("breakpoint on {}", words[1].to_uppercase())
+            }
+            ("w", Ok(args)) | ("watch", Ok(args)) if args.len() == 1 && args[0] >= 0 => {
+                self.add_watchpoint(args[0] as usize);
+                format!("watching {}", args[0])
+            }
+            ("d", Ok(args)) | ("delete", Ok(args)) if args.len() == 1 => {
+                match self.delete(args[0]) {
+                    true => format!("deleted {}", args[0]),
+                    false => format!("nothing set at {}", args[0]),
+                }
+            }
+            ("d", Err(_)) | ("delete", Err(_)) if words.len() == 2 => {
+                match self.delete_op_breakpoint(words[1]) {
+                    true => format!("deleted {}", words[1].to_uppercase()),
+                    false => format!("no breakpoint on {}", words[1].to_uppercase()),
+                }
+            }
+            ("i", Ok(args)) | ("input", Ok(args)) => {
+                args.iter().for_each(|i| self.push_input(*i));
+                format!("queued {} input(s)", args.len())
+            }
+            ("r", _) | ("regs", _) => self.registers(),
+            ("x", Ok(args)) | ("mem", Ok(args)) => {
+                let pc = self.machine.program_counter().max(0) as usize;
+                let start = args.first().map(|a| (*a).max(0) as usize).unwrap_or(pc);
+                let len = args.get(1).map(|a| (*a).max(0) as usize).unwrap_or(16);
+                self.dump(start, len)
+            }
+            ("mark", _) => {
+                self.mark();
+                String::from("marked")
+            }
+            ("diff", _) => match self.diff().to_string() {
+                diff if diff.is_empty() => String::from("nothing changed"),
+                diff => diff.trim_end().to_string(),
+            },
+            ("heatmap", Ok(args)) => {
+                let width = args.first().map(|w| (*w).max(0) as usize).unwrap_or(64);
+                self.diff().heatmap(width).trim_end().to_string()
+            }
+            ("o", _) | ("output", _) => {
+                let output: Vec<String> = self.output.iter().map(|o| o.to_string()).collect();
+                output.join(",")
+            }
+            ("h", _) | ("help", _) => String::from(HELP),
+            _ => format!("can't do '{}', try 'help'", line.trim()),
+        }
+    }
+
+    fn describe(&self, stop: Result<Stop, VmError>) -> String {
+        let what = match stop {
+            Ok(Stop::Breakpoint { address }) => format!("breakpoint at {}", address),
+            Ok(Stop::OpBreakpoint { address }) => format!("opcode breakpoint at {}", address),
+            Ok(Stop::Watchpoint { address, old, new }) => {
+                format!("[{}] changed from {} to {}", address, old, new)
+            }
+            Ok(Stop::Status(Status::Output(o))) => format!("output {}", o),
+            Ok(Stop::Status(Status::NeedsInput)) => String::from("waiting for input"),
+            Ok(Stop::Status(Status::Halted)) => String::from("halted"),
+            Ok(Stop::Status(Status::OutOfFuel { .. })) => String::from("out of fuel"),
+            Err(e) => format!("error: {}", e),
+        };
+        format!("{}\n{}", what, self.registers())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble;
+
+    fn debugger() -> Debugger {
+        let program = assemble(
+            "
+                    IN -> [n]
+            loop:   OUT [n]
+                    ADD [n], #-1 -> [n]
+                    JNZ [n], loop
+                    HLT
+            n:      .data 0
+            ",
+        )
+        .unwrap();
+        Debugger::new(Machine::new(program))
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut debugger = debugger();
+        debugger.add_breakpoint(8);
+        assert_eq!(debugger.cont().unwrap(), Stop::Status(Status::NeedsInput));
+        debugger.push_input(2);
+        assert_eq!(debugger.cont().unwrap(), Stop::Status(Status::Output(2)));
+        assert_eq!(debugger.cont().unwrap(), Stop::Breakpoint { address: 8 });
+        assert_eq!(debugger.cont().unwrap(), Stop::Status(Status::Output(1)));
+        assert!(debugger.delete(8));
+        assert_eq!(debugger.cont().unwrap(), Stop::Status(Status::Halted));
+        assert_eq!(debugger.output(), &vec![2, 1]);
+    }
+
+    #[test]
+    fn op_breakpoints_and_watchpoints() {
+        let mut debugger = debugger();
+        debugger.push_input(3);
+        debugger.add_op_breakpoint("jnz");
+        assert_eq!(debugger.cont().unwrap(), Stop::Status(Status::Output(3)));
+        assert_eq!(debugger.cont().unwrap(), Stop::OpBreakpoint { address: 8 });
+
+        assert!(debugger.delete_op_breakpoint("JNZ"));
+        debugger.add_watchpoint(12);
+        assert_eq!(debugger.cont().unwrap(), Stop::Status(Status::Output(2)));
+        assert_eq!(
+            debugger.cont().unwrap(),
+            Stop::Watchpoint {
+                address: 12,
+                old: 2,
+                new: 1
+            }
+        );
+    }
+
+    #[test]
+    fn commands() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.command("regs"), "pc 0  rb 0  IN -> [12]");
+        assert_eq!(debugger.command("input 1"), "queued 1 input(s)");
+        assert_eq!(
+            debugger.command("step 2"),
+            "output 1\npc 4  rb 0  ADD [12], #-1 -> [12]"
+        );
+        assert_eq!(debugger.command("break HLT"), "breakpoint on HLT");
+        assert_eq!(
+            debugger.command("c"),
+            "opcode breakpoint at 11\npc 11  rb 0  HLT"
+        );
+        assert_eq!(debugger.command("output"), "1");
+        assert_eq!(
+            debugger.command("frobnicate"),
+            "can't do 'frobnicate', try 'help'"
+        );
+    }
+
+    #[test]
+    fn diff_between_breakpoints() {
+        let mut debugger = debugger();
+        debugger.push_input(2);
+        debugger.add_breakpoint(8);
+        debugger.cont().unwrap();
+        assert_eq!(debugger.command("diff"), "          12  data  0 -> 2");
+        assert_eq!(debugger.command("mark"), "marked");
+        assert_eq!(debugger.command("diff"), "nothing changed");
+        debugger.cont().unwrap();
+        debugger.cont().unwrap();
+        assert_eq!(debugger.command("diff"), "          12  data  2 -> 1");
+        // a width of 0 is taken as 1, so each of the 13 cells gets a line
+        let heatmap = debugger.command("heatmap 0");
+        assert!(heatmap.starts_with("one character = 1 cell(s)\n"));
+        assert_eq!(heatmap.lines().count(), 1 + 13);
+    }
+}
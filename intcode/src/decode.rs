@@ -0,0 +1,192 @@
+use crate::opcode::*;
+use crate::{InstructionSet, Memory, VmError};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const NEAR: usize = 1 << 16;
+
+// Instructions already decoded, by address, so a loop decodes its body once instead of every
+// time round. Decoded operands only say where their values are, so an instruction stays good
+// until its opcode word changes, which is checked on every use: self-modifying programs see
+// their changes, and one cache can be shared by runs of variations on the same program. Using
+// the cache with a different instruction set empties it.
+#[derive(Debug, Clone, Default)]
+pub struct DecodeCache {
+    // by address, those below `NEAR` in a Vec, which is quickest to look up, and the rest in a
+    // map, so code far out doesn't allocate everything below it
+    near: Vec<Option<Entry>>,
+    far: HashMap<usize, Entry>,
+    // what the entries were decoded with
+    instructions: Option<Arc<InstructionSet>>,
+    hits: usize,
+    misses: usize,
+    invalidations: usize,
+}
+#[derive(Debug, Clone)]
+struct Entry {
+    word: i64,
+    relative_base: i64,
+    opcode: OpCode,
+}
+impl DecodeCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn decode(
+        &mut self,
+        instructions: &Arc<InstructionSet>,
+        data: &dyn Memory,
+        program_counter: i64,
+        relative_base: i64,
+    ) -> Result<OpCode, VmError> {
+        let state = CurrentState {
+            data,
+            program_counter,
+            relative_base,
+        };
+        if program_counter < 0 {
+            return instructions.decode(state);
+        }
+        match &self.instructions {
+            Some(decoded_with) if Arc::ptr_eq(decoded_with, instructions) => (),
+            _ => {
+                self.near.clear();
+                self.far.clear();
+                self.instructions = Some(instructions.clone());
+            }
+        }
+        let address = program_counter as usize;
+        let word = data.read(address);
+        let cached = match address < NEAR {
+            true => self.near.get_mut(address).and_then(Option::as_mut),
+            false => self.far.get_mut(&address),
+        };
+        match cached {
+            Some(entry) if entry.word == word => {
+                self.hits += 1;
+                if entry.relative_base != relative_base {
+                    entry.opcode.rebase(relative_base);
+                    entry.relative_base = relative_base;
+                }
+                return Ok(entry.opcode.clone());
+            }
+            Some(_) => self.invalidations += 1,
+            None => (),
+        }
+
+        self.misses += 1;
+        let opcode = instructions.decode(state)?;
+        let entry = Entry {
+            word,
+            relative_base,
+            opcode: opcode.clone(),
+        };
+        if address < NEAR {
+            if address >= self.near.len() {
+                self.near.resize(address + 1, None);
+            }
+            self.near[address] = Some(entry);
+        } else {
+            self.far.insert(address, entry);
+        }
+        Ok(opcode)
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    // cached instructions found overwritten
+    pub fn invalidations(&self) -> usize {
+        self.invalidations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble, Machine, Status};
+
+    #[test]
+    fn loops_decode_once() {
+        // output 3, 2, 1
+        let program = assemble(
+            "
+            loop:   OUT [n]
+                    ADD [n], #-1 -> [n]
+                    JNZ [n], loop
+                    HLT
+            n:      .data 3
+            ",
+        )
+        .unwrap();
+        let mut machine = Machine::new(program);
+        let mut output = Vec::new();
+        while let Status::Output(o) = machine.run_until().unwrap() {
+            output.push(o);
+        }
+        assert_eq!(output, vec![3, 2, 1]);
+        let cache = machine.decode_cache().unwrap();
+        assert_eq!(cache.misses(), 4);
+        assert_eq!(cache.hits(), 6);
+        assert_eq!(cache.invalidations(), 0);
+    }
+
+    #[test]
+    fn self_modifying_code() {
+        // The first pass outputs #6, then turns that OUT's immediate mode into position mode, so
+        // the second pass outputs [6] instead.
+        let program = vec![
+            104, 6, // OUT #6
+            1006, 19, 6,  // JZ [19], #6
+            99, // HLT
+            1101, 1, 0, 19, // ADD #1, #0 -> [19]
+            1001, 0, -100, 0, // ADD [0], #-100 -> [0]
+            1106, 0, 0, // JZ #0, #0
+            0, 0,
+        ];
+        for &cached in &[true, false] {
+            let mut machine = Machine::new(program.clone());
+            if !cached {
+                machine.set_decode_cache(None);
+            }
+            let mut output = Vec::new();
+            while let Status::Output(o) = machine.run_until().unwrap() {
+                output.push(o);
+            }
+            assert_eq!(output, vec![6, 1101]);
+            if cached {
+                assert_eq!(machine.decode_cache().unwrap().invalidations(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn shared_between_runs() {
+        // day02 style: the same program with different operands
+        let mut cache = DecodeCache::new();
+        for (a, b) in &[(1, 2), (30, 12), (-5, 5)] {
+            let mut machine = Machine::new(vec![1101, *a, *b, 7, 4, 7, 99, 0]);
+            machine.set_decode_cache(Some(cache));
+            assert_eq!(machine.run_until().unwrap(), Status::Output(a + b));
+            cache = machine.take_decode_cache().unwrap();
+        }
+        assert_eq!(cache.misses(), 2);
+        assert_eq!(cache.hits(), 4);
+    }
+
+    #[test]
+    fn far_out_code() {
+        // write `OUT #99` out at 2^40 and jump to it
+        let far = 1 << 40;
+        let program = vec![1101, 104, 0, far, 1101, 99, 0, far + 1, 1105, 1, far];
+        let mut machine = Machine::paged(program);
+        assert_eq!(machine.run_until().unwrap(), Status::Output(99));
+        assert_eq!(machine.decode_cache().unwrap().misses(), 4);
+    }
+}
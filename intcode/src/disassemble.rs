@@ -0,0 +1,304 @@
+use crate::opcode::*;
+use crate::{Memory, PagedMemory};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt;
+
+const DATA_WORDS_PER_LINE: usize = 8;
+
+pub enum Line {
+    Code { address: usize, opcode: OpCode },
+    Data { address: usize, words: Vec<i64> },
+}
+
+// A program image split into instructions and data. Only instructions reachable from address 0
+// by falling through or by a jump with an immediate target are decoded, leaving out ways an
+// immediate condition rules out; everything else, including code only reached through indirect
+// jumps, is listed as data.
+pub struct Listing {
+    lines: Vec<Line>,
+    // just the populated part of the image
+    words: PagedMemory,
+    labels: BTreeSet<usize>,
+}
+impl Listing {
+    pub fn lines(&self) -> &Vec<Line> {
+        &self.lines
+    }
+
+    pub fn labels(&self) -> &BTreeSet<usize> {
+        &self.labels
+    }
+}
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Code { address, opcode } => {
+                    if self.labels.contains(address) {
+                        writeln!(f, "{}:", label(*address))?;
+                    }
+                    writeln!(
+                        f,
+                        "{:>6}: {}",
+                        address,
+                        render(opcode, &|i| self.words.read(i as usize), &self.labels)
+                    )?;
+                }
+                Line::Data { address, words } => {
+                    let words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+                    writeln!(f, "{:>6}: .data {}", address, words.join(", "))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn label(address: usize) -> String {
+    format!("L{}", address)
+}
+
+// Render an instruction the way a listing shows it, e.g. `ADD #5, rb-1 -> [3]`.
+pub fn render_instruction(opcode: &OpCode, data: &dyn Memory) -> String {
+    render(opcode, &|i| data.read(i as usize), &BTreeSet::new())
+}
+
+fn render(opcode: &OpCode, word: &dyn Fn(i64) -> i64, labels: &BTreeSet<usize>) -> String {
+    let operand = |addr: &Addr, is_jump: bool| match addr {
+        Addr::Immediate(Imm(i)) => {
+            let val = word(*i);
+            if is_jump && val >= 0 && labels.contains(&(val as usize)) {
+                label(val as usize)
+            } else {
+                format!("#{}", val)
+            }
+        }
+        Addr::Position(Pos(i)) => format!("[{}]", word(*i)),
+        Addr::Relative(Rel(i, _)) => format!("rb{:+}", word(*i)),
+    };
+
+    let is_jump = matches!(opcode, OpCode::JumpIf { .. } | OpCode::JumpIfNot { .. });
+    let sources: Vec<String> = opcode
+        .sources()
+        .into_iter()
+        .enumerate()
+        .map(|(i, addr)| operand(addr, is_jump && i == 1))
+        .collect();
+    let mut s = String::from(opcode.mnemonic());
+    if !sources.is_empty() {
+        s += &format!(" {}", sources.join(", "));
+    }
+    if let Some(dest) = opcode.dest() {
+        s += &format!(" -> {}", operand(dest, false));
+    }
+    s
+}
+
+// where control can go after an instruction, as far as can be told statically
+pub(crate) struct Exits {
+    pub next: Option<usize>,
+    pub jump: Option<usize>,
+    // a jump that can be taken, to wherever a cell says
+    pub indirect: bool,
+}
+
+pub(crate) fn exits(opcode: &OpCode, address: usize, data: &dyn Memory) -> Exits {
+    let next = address + opcode.numFields() as usize + 1;
+    let immediate = |addr: &Addr| match addr {
+        Addr::Immediate(Imm(i)) => Some(data.read(*i as usize)),
+        _ => None,
+    };
+    let in_image =
+        |target: i64| (target >= 0 && (target as usize) < data.size()).then_some(target as usize);
+    let branch = |boolAddr: &Addr, jumpAddr: &Addr, jump_if_zero: bool| {
+        // with an immediate condition only one way is possible
+        let (can_jump, can_fall) = match immediate(boolAddr) {
+            Some(condition) => (
+                (condition == 0) == jump_if_zero,
+                (condition == 0) != jump_if_zero,
+            ),
+            None => (true, true),
+        };
+        Exits {
+            next: if can_fall { Some(next) } else { None },
+            jump: if can_jump {
+                immediate(jumpAddr).and_then(in_image)
+            } else {
+                None
+            },
+            indirect: can_jump && immediate(jumpAddr).is_none(),
+        }
+    };
+    match opcode {
+        OpCode::JumpIf { boolAddr, jumpAddr } => branch(boolAddr, jumpAddr, false),
+        OpCode::JumpIfNot { boolAddr, jumpAddr } => branch(boolAddr, jumpAddr, true),
+        OpCode::Halt => Exits {
+            next: None,
+            jump: None,
+            indirect: false,
+        },
+        _ => Exits {
+            next: Some(next),
+            jump: None,
+            indirect: false,
+        },
+    }
+}
+
+// What counts as code, for `disassemble` and `control_flow_graph` alike: every instruction
+// reachable from address 0 by falling through or by a jump with an immediate target, not
+// following ways an immediate condition rules out.
+pub(crate) struct Code {
+    pub instructions: BTreeMap<usize, OpCode>,
+    // where the jumps that were followed go
+    pub targets: BTreeSet<usize>,
+}
+
+pub(crate) fn find_code(data: &dyn Memory) -> Code {
+    let size = data.size();
+    let mut instructions: BTreeMap<usize, OpCode> = BTreeMap::new();
+    let mut targets = BTreeSet::new();
+    let mut todo = vec![0];
+    while let Some(address) = todo.pop() {
+        if address >= size || instructions.contains_key(&address) {
+            continue;
+        }
+        let opcode = match OpCode::try_from(CurrentState {
+            data,
+            program_counter: address as i64,
+            relative_base: 0,
+        }) {
+            Ok(opcode) => opcode,
+            Err(_) => continue,
+        };
+        if address + opcode.numFields() as usize >= size {
+            continue;
+        }
+        let exits = exits(&opcode, address, data);
+        if let Some(target) = exits.jump {
+            targets.insert(target);
+            todo.push(target);
+        }
+        todo.extend(exits.next);
+        instructions.insert(address, opcode);
+    }
+    Code {
+        instructions,
+        targets,
+    }
+}
+
+// A copy of `data` the same size that only holds its populated cells that aren't 0, so copying
+// sparse memory stays cheap.
+pub(crate) fn copy_populated(data: &dyn Memory) -> PagedMemory {
+    let mut words = PagedMemory::new();
+    for address in data.populated().into_iter().flatten() {
+        match data.read(address) {
+            0 => (),
+            word => *words.cell(address) = word,
+        }
+    }
+    words.try_extend(data.size());
+    words
+}
+
+pub fn disassemble(data: &dyn Memory) -> Listing {
+    let size = data.size();
+    let populated = data.populated();
+    let Code {
+        instructions: mut code,
+        targets: labels,
+    } = find_code(data);
+
+    // walk the image in order, keeping overlapping instructions out of each other's way and
+    // leaving out what was never populated
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < size {
+        if let Some(opcode) = code.remove(&address) {
+            let next = address + opcode.numFields() as usize + 1;
+            lines.push(Line::Code { address, opcode });
+            address = next;
+            continue;
+        }
+        let next_code = code
+            .range(address..)
+            .next()
+            .map(|(&a, _)| a)
+            .unwrap_or(size);
+        let range = populated.iter().find(|range| range.end > address);
+        let end = match range {
+            Some(range) if range.start <= address => range.end.min(next_code),
+            Some(range) => {
+                address = range.start.min(next_code);
+                continue;
+            }
+            None => {
+                address = next_code;
+                continue;
+            }
+        };
+        let end = end.min(address + DATA_WORDS_PER_LINE);
+        lines.push(Line::Data {
+            address,
+            words: (address..end).map(|a| data.read(a)).collect(),
+        });
+        address = end;
+    }
+    // a label is only useful if its instruction made it into the listing
+    let listed: BTreeSet<usize> = lines
+        .iter()
+        .filter_map(|line| match line {
+            Line::Code { address, .. } => Some(*address),
+            Line::Data { .. } => None,
+        })
+        .collect();
+    let labels = labels.intersection(&listed).cloned().collect();
+
+    Listing {
+        lines,
+        words: copy_populated(data),
+        labels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing() {
+        let data = vec![3, 13, 1006, 13, 12, 2101, 5, -1, 3, 1105, 1, 0, 99, 7, 7];
+        let expected = [
+            "L0:",
+            "     0: IN -> [13]",
+            "     2: JZ [13], L12",
+            "     5: ADD #5, rb-1 -> [3]",
+            "     9: JNZ #1, L0",
+            "L12:",
+            "    12: HLT",
+            "    13: .data 7, 7",
+        ];
+        assert_eq!(disassemble(&data).to_string(), expected.join("\n") + "\n");
+    }
+
+    #[test]
+    fn data_lines_are_split() {
+        let data: Vec<i64> = vec![99].into_iter().chain(100..120).collect();
+        let listing = disassemble(&data);
+        assert_eq!(listing.lines().len(), 4);
+        assert!(listing.labels().is_empty());
+    }
+
+    #[test]
+    fn sparse_memory() {
+        let mut data = PagedMemory::from(vec![104, 7, 99]);
+        *data.cell(1_000_000_000_000) = 5;
+        // only the pages in use are listed
+        let listing = disassemble(&data).to_string();
+        assert!(listing.starts_with("     0: OUT #7\n     2: HLT\n     3: .data 0, 0,"));
+        assert!(listing.ends_with("  4091: .data 0, 0, 0, 0, 0\n1000000000000: .data 5\n"));
+        assert_eq!(listing.lines().count(), 2 + 512 + 1);
+    }
+}
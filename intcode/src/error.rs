@@ -0,0 +1,68 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    InvalidOpCode { address: i64, opcode: i64 },
+    InvalidMode { address: i64, mode: i64 },
+    // `address` is that of the offending operand, not of the instruction
+    WriteToImmediate { address: i64 },
+    NegativeAddress { address: i64 },
+    // a relative address or the relative base didn't fit in a word; `address` is that of the
+    // offending operand
+    AddressOverflow { address: i64 },
+    InputExhausted { address: i64 },
+    OutOfFuel { address: i64, steps: usize },
+    // a custom instruction refused its operands
+    InstructionFault { address: i64, opcode: i64 },
+    // an ADD or MUL result didn't fit in a word
+    Overflow { address: i64 },
+}
+use VmError::*;
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidOpCode { address, opcode } => {
+                write!(f, "{} at address {} is an invalid opcode", opcode, address)
+            }
+            InvalidMode { address, mode } => write!(
+                f,
+                "{} is not a valid mode, must be in [0,1,2] (instruction at address {})",
+                mode, address
+            ),
+            WriteToImmediate { address } => {
+                write!(f, "write to immediate operand at address {}", address)
+            }
+            NegativeAddress { address } => write!(f, "{} is a negative address", address),
+            AddressOverflow { address } => write!(
+                f,
+                "address overflow in the relative operand at address {}",
+                address
+            ),
+            InputExhausted { address } => {
+                write!(
+                    f,
+                    "instruction at address {} read past the end of input",
+                    address
+                )
+            }
+            OutOfFuel { address, steps } => write!(
+                f,
+                "ran out of fuel before the instruction at address {}, after {} steps",
+                address, steps
+            ),
+            InstructionFault { address, opcode } => write!(
+                f,
+                "instruction {} at address {} faulted on its operands",
+                opcode, address
+            ),
+            Overflow { address } => write!(
+                f,
+                "arithmetic overflow in the instruction at address {}",
+                address
+            ),
+        }
+    }
+}
+impl Error for VmError {}
@@ -0,0 +1,459 @@
+mod interpret;
+use crate::opcode::*;
+use crate::{run_symbolic, Limits, Machine, Memory, Overflow, Status, SymbolicError, Var, VmError};
+use interpret::{interpret, Stop};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+
+const FUEL: usize = 10_000;
+// Dense memory allocates everything up to the highest address written, and programs that rewrite
+// their own operands or move the relative base around can reach for any address at all, so runs
+// stop short of writing anything past this.
+const MAX_ADDRESS: usize = 1 << 16;
+const INPUTS: usize = 32;
+
+// A xorshift generator, so fuzzing needs nothing beyond std and any failure can be replayed
+// from its seed.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift never leaves 0
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // in 0..n
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    // true one time in `n`
+    pub fn one_in(&mut self, n: u64) -> bool {
+        self.below(n) == 0
+    }
+}
+
+// (opcode, number of operands, whether the last one is written to) for the standard set
+const SHAPES: [(i64, usize, bool); 10] = [
+    (1, 3, true),
+    (2, 3, true),
+    (3, 1, true),
+    (4, 1, false),
+    (5, 2, false),
+    (6, 2, false),
+    (7, 3, true),
+    (8, 3, true),
+    (9, 1, false),
+    (99, 0, false),
+];
+
+// How many words an instruction takes, worked out independently of the decoder.
+fn length(opcode: i64) -> Option<usize> {
+    let shape = SHAPES.iter().find(|(code, ..)| *code == opcode % 100)?;
+    Some(shape.1 + 1)
+}
+
+// A random program that is well formed as written: every instruction has a valid opcode and
+// modes, nothing is written through an immediate, and position operands point inside the image.
+// What it does once it runs, including rewriting itself, is up to chance.
+#[derive(Debug, Clone)]
+pub struct Generated {
+    pub data: Vec<i64>,
+    pub input: Vec<i64>,
+}
+
+pub fn generate(rng: &mut Rng, size: usize) -> Generated {
+    let mut data = Vec::new();
+    let mut starts = Vec::new();
+    while data.len() < size {
+        let (code, operands, writes) = SHAPES[rng.below(SHAPES.len() as u64) as usize];
+        // halting is rare, so programs get some way before they stop
+        if code == 99 && !rng.one_in(4) {
+            continue;
+        }
+        starts.push(data.len());
+        let mut word = code;
+        let mut fields = Vec::new();
+        for n in 0..operands {
+            let written = writes && n == operands - 1;
+            let mode = match rng.below(3) {
+                1 if written => 0,
+                mode => mode as i64,
+            };
+            word += mode * 10i64.pow(n as u32 + 2);
+            let field = match mode {
+                // pointing anywhere, code included
+                0 => rng.below(size as u64) as i64,
+                // now and then far enough to overflow the relative address or base
+                1 if code == 9 && rng.one_in(4) => edge(rng),
+                2 if rng.one_in(8) => edge(rng),
+                // small enough to stay in range of a small relative base
+                2 => rng.below(16) as i64,
+                _ => value(rng),
+            };
+            fields.push(field);
+        }
+        data.push(word);
+        data.extend(fields);
+    }
+    // immediate jump targets mostly go to the start of an instruction
+    for &i in &starts {
+        let immediate_jump = matches!(data[i] % 100, 5 | 6) && (data[i] / 1000) % 10 == 1;
+        if immediate_jump && !rng.one_in(8) {
+            data[i + 2] = starts[rng.below(starts.len() as u64) as usize] as i64;
+        }
+    }
+    let input = (0..INPUTS).map(|_| value(rng)).collect();
+    Generated { data, input }
+}
+
+// mostly small, sometimes big enough to overflow when multiplied
+fn value(rng: &mut Rng) -> i64 {
+    match rng.below(16) {
+        0 => rng.next_u64() as i64 >> 16,
+        1 => 0,
+        2 => 1,
+        _ => rng.below(41) as i64 - 20,
+    }
+}
+
+// at or near either end of a word, or just below 0
+fn edge(rng: &mut Rng) -> i64 {
+    let near = rng.below(4) as i64;
+    match rng.below(3) {
+        0 => i64::MAX - near,
+        1 => i64::MIN + near,
+        _ => -1 - near,
+    }
+}
+
+// Run `program` on differently configured machines in lockstep and check that they agree at
+// every step with each other, that every instruction decodes to the operands its modes say and
+// advances the program counter by its length, and that they end up where the separately written
+// interpreter in `interpret` and the symbolic interpreter do. Those are the independent
+// implementations there are, now that every day runs on this one machine. Where the symbolic
+// check can't be made, says why.
+pub fn check(program: &Generated) -> Result<Option<&'static str>, String> {
+    let limits = Limits {
+        fuel: Some(FUEL),
+        time: None,
+    };
+    let mut reference = Machine::new(program.data.clone());
+    reference.set_decode_cache(None);
+    let mut cached = Machine::new(program.data.clone());
+    let mut paged = Machine::paged(program.data.clone());
+    let mut wrapping = Machine::new(program.data.clone());
+    wrapping.set_overflow(Overflow::Wrap);
+    reference.set_limits(limits);
+    cached.set_limits(limits);
+    paged.set_limits(limits);
+    wrapping.set_limits(limits);
+    for &i in &program.input {
+        reference.push_input(i);
+        cached.push_input(i);
+        paged.push_input(i);
+        wrapping.push_input(i);
+    }
+
+    let mut output = Vec::new();
+    let mut stopped_short = false;
+    let end = loop {
+        let pc = reference.program_counter();
+        // the instruction may overwrite itself, so remember what it was
+        let word = reference.data().read(pc.max(0) as usize);
+        let decoded = reference.current();
+        if let Ok(opcode) = &decoded {
+            check_decoding(opcode, reference.data(), pc)?;
+            let dest = opcode
+                .dest()
+                .and_then(|addr| addr.resolve(reference.data()).ok());
+            if dest.is_some_and(|address| address > MAX_ADDRESS) {
+                stopped_short = true;
+                break Ok(None);
+            }
+        }
+
+        let result = reference.step();
+        let agree = |name: &str, other: Result<Option<Status>, VmError>| match other == result {
+            true => Ok(()),
+            false => Err(format!(
+                "step {} at {}: {} machine gave {:?}, expected {:?}",
+                reference.steps(),
+                pc,
+                name,
+                other,
+                result
+            )),
+        };
+        agree("cached", cached.step())?;
+        agree("paged", paged.step())?;
+        // wrapping only differs once trapping would have stopped
+        if !matches!(result, Err(VmError::Overflow { .. })) {
+            agree("wrapping", wrapping.step())?;
+        }
+
+        if let (Ok(opcode), Ok(None)) = (&decoded, &result) {
+            let jumps = matches!(opcode, OpCode::JumpIf { .. } | OpCode::JumpIfNot { .. });
+            let advanced = reference.program_counter() - pc;
+            let expected = length(word).unwrap();
+            if !jumps && advanced != expected as i64 {
+                return Err(format!(
+                    "instruction at {} advanced the program counter by {}, not {}",
+                    pc, advanced, expected
+                ));
+            }
+        }
+        match result {
+            Ok(Some(Status::Output(o))) => output.push(o),
+            Ok(None) => (),
+            end => break end,
+        }
+    };
+    if !same_memory(reference.data(), cached.data()) || !same_memory(reference.data(), paged.data())
+    {
+        return Err(String::from("machines finished with different memory"));
+    }
+
+    let interpreted = interpret(&program.data, &program.input, FUEL, MAX_ADDRESS);
+    let stop = match end {
+        _ if stopped_short => Stop::TooFar,
+        Ok(Some(Status::Halted)) => Stop::Halted,
+        Ok(Some(Status::NeedsInput)) => Stop::NeedsInput,
+        Ok(Some(Status::OutOfFuel { .. })) => Stop::OutOfFuel,
+        _ => Stop::Failed,
+    };
+    let expected = (stop, reference.steps(), reference.program_counter());
+    let got = (
+        interpreted.stop,
+        interpreted.steps,
+        interpreted.program_counter,
+    );
+    if got != expected || interpreted.output != output {
+        return Err(format!(
+            "interpreter stopped {:?} after {} steps at {} with output {:?}, expected {:?} after \
+             {} steps at {} with output {:?}",
+            got.0, got.1, got.2, interpreted.output, expected.0, expected.1, expected.2, output
+        ));
+    }
+    if !same_memory(reference.data(), &interpreted.memory) {
+        return Err(String::from("interpreter finished with different memory"));
+    }
+
+    // the symbolic interpreter can't wait for input or run out of fuel, and doesn't trap
+    // overflow in values computed from input
+    match end {
+        _ if stopped_short => return Ok(Some("stopped short of writing too far")),
+        Ok(Some(Status::NeedsInput)) => return Ok(Some("needed more input")),
+        Ok(Some(Status::OutOfFuel { .. })) => return Ok(Some("ran out of fuel")),
+        Err(VmError::Overflow { .. }) => return Ok(Some("overflowed")),
+        _ => (),
+    }
+    let unknown = Cell::new(false);
+    let var = |var: Var| match var {
+        Var::Input(n) => program.input[n],
+        _ => {
+            unknown.set(true);
+            0
+        }
+    };
+    let symbolic = match end {
+        Ok(_) => run_symbolic(&program.data, &[], FUEL),
+        // reads through addresses that depend on input can't fail symbolically, so where the
+        // machine failed just check that the symbolic run got to the same instruction
+        Err(_) => run_symbolic(&program.data, &[], reference.steps()),
+    };
+    match (symbolic, end) {
+        (Ok(run), Ok(_)) => {
+            let outputs: Vec<i64> = run.outputs().iter().map(|o| o.eval(&var)).collect();
+            let memory = (0..reference.data().size()).map(|a| run.cell(a).eval(&var));
+            let memory: Vec<i64> = memory.collect();
+            if unknown.get() {
+                return Ok(Some("read memory the symbolic run can't give"));
+            }
+            if outputs != output || memory != *reference.data() {
+                return Err(format!(
+                    "symbolic run output {:?}, expected {:?}",
+                    outputs, output
+                ));
+            }
+        }
+        (Err(SymbolicError::Vm(VmError::OutOfFuel { address, .. })), Err(_))
+            if address == reference.program_counter() => {}
+        (Err(SymbolicError::Depends { .. }), _) => {
+            return Ok(Some("depended on input symbolically"))
+        }
+        (symbolic, end) => {
+            return Err(format!(
+                "symbolic run gave {:?}, expected {:?}",
+                symbolic.map(|run| run.outputs().to_vec()),
+                end
+            ))
+        }
+    }
+    // then that the instruction the machine failed on fails the same way symbolically. Once
+    // input has been read, an operand whose address depends on it can't fail symbolically, so
+    // a later one may fail instead, or none.
+    let read_input = reference.pending_input().len() < program.input.len();
+    if let Err(error) = end {
+        match run_symbolic(&program.data, &[], reference.steps() + 1) {
+            Err(SymbolicError::Vm(e)) if e == error => (),
+            _ if read_input => return Ok(Some("failed on an address computed from input")),
+            symbolic => {
+                return Err(format!(
+                    "symbolic run gave {:?}, expected {:?}",
+                    symbolic.map(|run| run.outputs().to_vec()),
+                    error
+                ))
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn same_memory(a: &dyn Memory, b: &dyn Memory) -> bool {
+    (0..a.size().max(b.size())).all(|address| a.read(address) == b.read(address))
+}
+
+// Every operand has to come out in the mode its digit gives.
+fn check_decoding(opcode: &OpCode, data: &dyn Memory, pc: i64) -> Result<(), String> {
+    let word = data.read(pc as usize);
+    let expected = length(word).ok_or(format!("{} at {} decoded", word, pc))?;
+    if opcode.numFields() as usize + 1 != expected {
+        return Err(format!(
+            "{} at {} decoded with {} fields, not {}",
+            word,
+            pc,
+            opcode.numFields(),
+            expected - 1
+        ));
+    }
+    for addr in opcode.sources().into_iter().chain(opcode.dest()) {
+        let (field, mode) = match addr {
+            Addr::Position(Pos(i)) => (*i, 0),
+            Addr::Immediate(Imm(i)) => (*i, 1),
+            Addr::Relative(Rel(i, _)) => (*i, 2),
+        };
+        let digit = (word / 10i64.pow((field - pc) as u32 + 1)) % 10;
+        if digit != mode {
+            return Err(format!(
+                "operand {} of {} at {} decoded in mode {}, not {}",
+                field - pc,
+                word,
+                pc,
+                mode,
+                digit
+            ));
+        }
+    }
+    Ok(())
+}
+
+// How many programs weren't checked symbolically, by why not.
+pub type Skipped = BTreeMap<&'static str, u64>;
+
+// Check `runs` programs generated from seeds `seed..`, returning the seed of the first that
+// fails, panics included, and why.
+pub fn fuzz(seed: u64, runs: u64, size: usize) -> Result<Skipped, (u64, String)> {
+    let mut skipped = Skipped::new();
+    for seed in seed..seed + runs {
+        let program = generate(&mut Rng::new(seed), size);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| check(&program)));
+        match result {
+            Ok(Ok(None)) => (),
+            Ok(Ok(Some(why))) => *skipped.entry(why).or_insert(0) += 1,
+            Ok(Err(why)) => return Err((seed, why)),
+            Err(panic) => {
+                let why = panic
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+                    .unwrap_or_default();
+                return Err((seed, format!("panicked: {}", why)));
+            }
+        }
+    }
+    Ok(skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::iter;
+
+    #[test]
+    fn random_programs_agree() {
+        let skipped =
+            fuzz(0, 500, 64).unwrap_or_else(|(seed, why)| panic!("seed {}: {}", seed, why));
+        // most are checked symbolically too
+        assert!(skipped.values().sum::<u64>() < 250);
+    }
+
+    #[test]
+    fn generates_every_opcode_and_mode() {
+        let mut rng = Rng::new(1);
+        let mut opcodes = std::collections::BTreeSet::new();
+        let mut relative = false;
+        for _ in 0..50 {
+            let program = generate(&mut rng, 64);
+            let mut pc = 0;
+            while pc < program.data.len() {
+                let word = program.data[pc];
+                opcodes.insert(word % 100);
+                relative |= (word / 100) % 10 == 2;
+                pc += length(word).unwrap();
+            }
+        }
+        assert_eq!(opcodes.len(), SHAPES.len());
+        assert!(relative);
+    }
+
+    #[test]
+    fn reaches_address_overflow() {
+        let overflowed = (0..500).filter(|&seed| {
+            let program = generate(&mut Rng::new(seed), 64);
+            let mut machine = Machine::paged(program.data);
+            machine.set_limits(Limits {
+                fuel: Some(FUEL),
+                time: None,
+            });
+            program.input.iter().for_each(|&i| machine.push_input(i));
+            let end = iter::repeat_with(|| machine.step()).find(|result| result != &Ok(None));
+            matches!(end, Some(Err(VmError::AddressOverflow { .. })))
+        });
+        assert!(overflowed.count() > 0);
+
+        // what the machine and the symbolic interpreter once got wrong
+        for data in &[
+            vec![109, i64::MAX, 109, 1, 99],
+            vec![109, i64::MIN, 204, -1, 99],
+        ] {
+            let program = Generated {
+                data: data.clone(),
+                input: Vec::new(),
+            };
+            assert_eq!(check(&program), Ok(None));
+        }
+    }
+
+    #[test]
+    fn catches_relative_mode_decoded_as_position() {
+        // what day07's own machine once did with `204, 5`
+        let wrong = OpCode::Write {
+            opAddr1: Addr::Position(Pos(1)),
+        };
+        assert_eq!(
+            check_decoding(&wrong, &vec![204, 5], 0),
+            Err(String::from(
+                "operand 1 of 204 at 0 decoded in mode 0, not 2"
+            ))
+        );
+    }
+}
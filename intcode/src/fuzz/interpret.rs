@@ -0,0 +1,201 @@
+use std::convert::TryFrom;
+
+// Where `interpret` stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Halted,
+    NeedsInput,
+    OutOfFuel,
+    // about to write past the limit
+    TooFar,
+    // on any error at all; which one is the machine's business
+    Failed,
+}
+
+#[derive(Debug)]
+pub struct Interpreted {
+    pub memory: Vec<i64>,
+    pub output: Vec<i64>,
+    pub program_counter: i64,
+    pub steps: usize,
+    pub stop: Stop,
+}
+
+// A second intcode implementation, written from the puzzle text and sharing nothing with the
+// machine, so the two can be checked against each other. It is as plain as it can be rather than
+// fast, and knows only the standard instructions, trapping on overflow. It runs `program` until
+// it stops, at most `fuel` instructions, without writing past `limit`.
+pub fn interpret(program: &[i64], input: &[i64], fuel: usize, limit: usize) -> Interpreted {
+    let mut state = State {
+        memory: program.to_vec(),
+        input,
+        output: Vec::new(),
+        pc: 0,
+        base: 0,
+        steps: 0,
+    };
+    let stop = loop {
+        // running off either end of memory halts too
+        if state.pc < 0 || state.pc as usize >= state.memory.len() {
+            break Stop::Halted;
+        }
+        if let Err(stop) = state.step(fuel, limit) {
+            break stop;
+        }
+    };
+    Interpreted {
+        memory: state.memory,
+        output: state.output,
+        program_counter: state.pc,
+        steps: state.steps,
+        stop,
+    }
+}
+
+struct State<'a> {
+    memory: Vec<i64>,
+    input: &'a [i64],
+    output: Vec<i64>,
+    pc: i64,
+    base: i64,
+    steps: usize,
+}
+impl State<'_> {
+    fn get(&self, address: i64) -> i64 {
+        let cell = usize::try_from(address)
+            .ok()
+            .and_then(|a| self.memory.get(a));
+        cell.cloned().unwrap_or(0)
+    }
+
+    fn mode(&self, n: i64) -> i64 {
+        self.get(self.pc) / 10i64.pow(n as u32 + 2) % 10
+    }
+
+    // where operand `n` of the current instruction points, if anywhere
+    fn address(&self, n: i64) -> Option<i64> {
+        let field = self.pc + 1 + n;
+        let address = match self.mode(n) {
+            0 => self.get(field),
+            1 => field,
+            _ => self.base.checked_add(self.get(field))?,
+        };
+        (address >= 0).then_some(address)
+    }
+
+    fn value(&self, n: i64) -> Result<i64, Stop> {
+        let address = self.address(n).ok_or(Stop::Failed)?;
+        Ok(self.get(address))
+    }
+
+    // The checks come in the order the fuzzer makes them: it stops short of writing too far
+    // before it asks the machine to step, which runs out of fuel before it decodes, which it
+    // does before waiting for input.
+    fn step(&mut self, fuel: usize, limit: usize) -> Result<(), Stop> {
+        let word = self.get(self.pc);
+        let operands = match word % 100 {
+            _ if word < 0 => None,
+            1 | 2 | 7 | 8 => Some(3),
+            3 | 4 | 9 => Some(1),
+            5 | 6 => Some(2),
+            99 => Some(0),
+            _ => None,
+        };
+        let operands = operands.filter(|&n| (0..n).all(|n| self.mode(n) <= 2));
+        let writes = matches!(word % 100, 1 | 2 | 3 | 7 | 8);
+        let dest = match operands {
+            Some(n) if writes => self.address(n - 1),
+            _ => None,
+        };
+        if dest.is_some_and(|address| address > limit as i64) {
+            return Err(Stop::TooFar);
+        }
+        if self.steps == fuel {
+            return Err(Stop::OutOfFuel);
+        }
+        let operands = operands.ok_or(Stop::Failed)?;
+        if word % 100 == 3 && self.input.is_empty() {
+            return Err(Stop::NeedsInput);
+        }
+        if writes && self.mode(operands - 1) == 1 {
+            return Err(Stop::Failed);
+        }
+
+        let mut next = self.pc + 1 + operands;
+        let written = match word % 100 {
+            1 => self.value(0)?.checked_add(self.value(1)?),
+            2 => self.value(0)?.checked_mul(self.value(1)?),
+            3 => {
+                let (first, rest) = self.input.split_first().unwrap();
+                self.input = rest;
+                Some(*first)
+            }
+            7 => Some((self.value(0)? < self.value(1)?) as i64),
+            8 => Some((self.value(0)? == self.value(1)?) as i64),
+            code => {
+                match code {
+                    4 => self.output.push(self.value(0)?),
+                    5 | 6 if (self.value(0)? != 0) == (code == 5) => {
+                        next = self.value(1)?;
+                        if next < 0 {
+                            return Err(Stop::Failed);
+                        }
+                    }
+                    9 => self.base = self.base.checked_add(self.value(0)?).ok_or(Stop::Failed)?,
+                    99 => next = -1,
+                    _ => (),
+                }
+                None
+            }
+        };
+        if writes {
+            let value = written.ok_or(Stop::Failed)?;
+            let address = dest.ok_or(Stop::Failed)? as usize;
+            if address >= self.memory.len() {
+                self.memory.resize(address + 1, 0);
+            }
+            self.memory[address] = value;
+        }
+        self.pc = next;
+        self.steps += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_day09_examples() {
+        let quine = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let run = interpret(&quine, &[], 1000, 1000);
+        assert_eq!(run.stop, Stop::Halted);
+        assert_eq!(run.output, quine);
+        assert_eq!(run.memory.len(), 102);
+
+        let big = interpret(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0], &[], 1000, 1000);
+        assert_eq!(big.output, vec![1219070632396864]);
+        assert_eq!(big.steps, 3);
+    }
+
+    #[test]
+    fn stops() {
+        let echo = [3, 7, 4, 7, 1105, 1, 0, 0];
+        let run = interpret(&echo, &[5, 6], 1000, 10);
+        assert_eq!(
+            (run.stop, run.output, run.program_counter),
+            (Stop::NeedsInput, vec![5, 6], 0)
+        );
+        assert_eq!(interpret(&echo, &[5, 6], 4, 10).stop, Stop::OutOfFuel);
+        assert_eq!(
+            interpret(&[1101, 1, 1, 11, 99], &[], 10, 10).stop,
+            Stop::TooFar
+        );
+        let overflow = interpret(&[1102, i64::MAX, 2, 0, 99], &[], 10, 10);
+        assert_eq!((overflow.stop, overflow.steps), (Stop::Failed, 0));
+        assert_eq!(interpret(&[1103, 0, 99], &[1], 10, 10).stop, Stop::Failed);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{Machine, Memory, Status, VmError};
+use std::collections::VecDeque;
+use std::iter;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+// Where a machine gets input from when it runs out. `None` means there will never be more.
+pub trait InputSource {
+    fn recv(&mut self) -> Option<i64>;
+}
+impl InputSource for Receiver<i64> {
+    // blocks until the sending end sends something or hangs up
+    fn recv(&mut self) -> Option<i64> {
+        Receiver::recv(self).ok()
+    }
+}
+impl InputSource for VecDeque<i64> {
+    fn recv(&mut self) -> Option<i64> {
+        self.pop_front()
+    }
+}
+impl InputSource for std::vec::IntoIter<i64> {
+    fn recv(&mut self) -> Option<i64> {
+        self.next()
+    }
+}
+
+pub trait OutputSink {
+    fn send(&mut self, i: i64);
+}
+impl OutputSink for Sender<i64> {
+    // A machine downstream may already have halted and hung up, which is how most networks end,
+    // so whatever it would have received is dropped.
+    fn send(&mut self, i: i64) {
+        Sender::send(self, i).ok();
+    }
+}
+// send to every one of several machines
+impl OutputSink for Vec<Sender<i64>> {
+    fn send(&mut self, i: i64) {
+        for sender in self.iter_mut() {
+            OutputSink::send(sender, i);
+        }
+    }
+}
+impl OutputSink for Vec<i64> {
+    fn send(&mut self, i: i64) {
+        self.push(i);
+    }
+}
+
+// Input for a program that speaks ASCII: the character codes of `line`, then a newline.
+pub fn ascii_line(line: &str) -> Vec<i64> {
+    line.chars()
+        .chain(iter::once('\n'))
+        .map(|c| c as i64)
+        .collect()
+}
+
+// Output from a program that speaks ASCII: the character, if it is one, or else the number on a
+// line of its own.
+pub fn ascii_output(o: i64) -> String {
+    match o {
+        0..=127 => (o as u8 as char).to_string(),
+        _ => format!("\n{}\n", o),
+    }
+}
+
+impl<M: Memory> Machine<M> {
+    // Run to completion, pulling input from `input` whenever the machine needs it.
+    pub fn run_io(
+        &mut self,
+        input: &mut dyn InputSource,
+        output: &mut dyn OutputSink,
+    ) -> Result<(), VmError> {
+        loop {
+            match self.run_until()? {
+                Status::Output(o) => output.send(o),
+                Status::Halted => return Ok(()),
+                Status::NeedsInput => match input.recv() {
+                    Some(i) => self.push_input(i),
+                    None => {
+                        return Err(VmError::InputExhausted {
+                            address: self.program_counter(),
+                        })
+                    }
+                },
+                Status::OutOfFuel { address, steps } => {
+                    return Err(VmError::OutOfFuel { address, steps })
+                }
+            }
+        }
+    }
+}
+
+// Run `machine` on its own thread. Dropping every `Sender` for `input` while the machine waits
+// on it ends the run with `InputExhausted`.
+pub fn spawn<M, O>(
+    mut machine: Machine<M>,
+    mut input: Receiver<i64>,
+    mut output: O,
+) -> JoinHandle<Result<Machine<M>, VmError>>
+where
+    M: Memory + Send + 'static,
+    O: OutputSink + Send + 'static,
+{
+    thread::spawn(move || {
+        machine.run_io(&mut input, &mut output)?;
+        Ok(machine)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    // day07 amplifier programs: read a phase setting, then turn signals into signals
+    const FEEDBACK: [i64; 29] = [
+        3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28,
+        1005, 28, 6, 99, 0, 0, 5,
+    ];
+
+    #[test]
+    fn run_io_with_queues() {
+        let mut machine = Machine::new(vec![3, 11, 3, 12, 1, 11, 12, 11, 4, 11, 99]);
+        let mut output: Vec<i64> = Vec::new();
+        machine
+            .run_io(&mut vec![40, 2].into_iter(), &mut output)
+            .unwrap();
+        assert_eq!(output, vec![42]);
+
+        let mut machine = Machine::new(vec![3, 11, 3, 12, 1, 11, 12, 11, 4, 11, 99]);
+        let error = machine
+            .run_io(&mut VecDeque::from(vec![40]), &mut output)
+            .unwrap_err();
+        assert_eq!(error, VmError::InputExhausted { address: 2 });
+    }
+
+    #[test]
+    fn threaded_feedback_loop() {
+        let phases = [9, 8, 7, 6, 5];
+        let (senders, receivers): (Vec<_>, Vec<_>) = phases.iter().map(|_| channel()).unzip();
+        for (sender, phase) in senders.iter().zip(phases.iter()) {
+            sender.send(*phase).unwrap();
+        }
+        senders[0].send(0).unwrap();
+
+        // amplifier i sends to amplifier i + 1, and the last one also to us
+        let (result_sender, results) = channel();
+        let mut outputs: Vec<Vec<Sender<i64>>> = (1..phases.len())
+            .map(|i| vec![senders[i].clone()])
+            .collect();
+        outputs.push(vec![senders[0].clone(), result_sender]);
+        drop(senders);
+
+        let handles: Vec<_> = receivers
+            .into_iter()
+            .zip(outputs)
+            .map(|(input, output)| spawn(Machine::new(FEEDBACK.to_vec()), input, output))
+            .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap().unwrap().is_halted());
+        }
+        assert_eq!(results.iter().last(), Some(139629729));
+    }
+
+    #[test]
+    fn ascii() {
+        assert_eq!(ascii_line("go N"), vec![103, 111, 32, 78, 10]);
+        assert_eq!(ascii_line(""), vec![10]);
+        let output: String = [72, 105, 10, 128, 33]
+            .iter()
+            .map(|&o| ascii_output(o))
+            .collect();
+        assert_eq!(output, "Hi\n\n128\n!");
+    }
+}
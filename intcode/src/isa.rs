@@ -0,0 +1,493 @@
+use crate::opcode::*;
+use crate::VmError;
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+// Modes are the digits above an opcode's two, and an i64 has 19 digits.
+const MAX_OPERANDS: usize = 17;
+
+// What a custom instruction does with the values of its source operands. Anything left `None`
+// doesn't happen: no write, no output, and no jump, so execution carries on after the operands.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Effect {
+    pub write: Option<i64>,
+    pub output: Option<i64>,
+    pub jump: Option<i64>,
+}
+
+type Build = fn(&dyn Fn(usize) -> Result<Addr, VmError>) -> Result<OpCode, VmError>;
+// `None` is a fault, e.g. dividing by zero
+type Semantics = dyn Fn(&[i64]) -> Option<Effect> + Send + Sync;
+
+#[derive(Clone)]
+enum Kind {
+    // one of the `OpCode` variants the VM knows how to run itself
+    Standard(Build),
+    Custom(Arc<Semantics>),
+}
+
+// One opcode: its number, how many operands it takes and what it does with them. An instruction
+// reads `sources` operands, then writes to one more if `writes` is set.
+#[derive(Clone)]
+pub struct Instruction {
+    pub code: i64,
+    pub mnemonic: &'static str,
+    pub sources: usize,
+    pub writes: bool,
+    kind: Kind,
+}
+impl Instruction {
+    pub fn new<F>(code: i64, mnemonic: &'static str, sources: usize, writes: bool, f: F) -> Self
+    where
+        F: Fn(&[i64]) -> Option<Effect> + Send + Sync + 'static,
+    {
+        Instruction {
+            code,
+            mnemonic,
+            sources,
+            writes,
+            kind: Kind::Custom(Arc::new(f)),
+        }
+    }
+
+    fn standard(
+        code: i64,
+        mnemonic: &'static str,
+        sources: usize,
+        writes: bool,
+        build: Build,
+    ) -> Self {
+        Instruction {
+            code,
+            mnemonic,
+            sources,
+            writes,
+            kind: Kind::Standard(build),
+        }
+    }
+
+    pub fn numFields(&self) -> usize {
+        self.sources + self.writes as usize
+    }
+
+    pub(crate) fn execute(&self, operands: &[i64]) -> Option<Effect> {
+        match &self.kind {
+            Kind::Custom(f) => f(operands),
+            Kind::Standard(_) => unreachable!("standard instructions run as their own OpCode"),
+        }
+    }
+}
+impl fmt::Debug for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, {} sources{})",
+            self.mnemonic,
+            self.code,
+            self.sources,
+            if self.writes { ", writes" } else { "" }
+        )
+    }
+}
+
+// Opcodes by number. A machine decodes with one of these, the standard day09 set unless it's
+// given another, so experimental instructions can be added without touching the VM.
+#[derive(Debug, Clone)]
+pub struct InstructionSet {
+    // indexed by opcode, which is the last two digits of an instruction
+    instructions: Vec<Option<Arc<Instruction>>>,
+}
+impl Default for InstructionSet {
+    fn default() -> Self {
+        InstructionSet {
+            instructions: vec![None; 100],
+        }
+    }
+}
+impl InstructionSet {
+    // an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn standard() -> Self {
+        (**Self::shared_standard()).clone()
+    }
+
+    // built once, for all the machines that don't use anything else
+    pub(crate) fn shared_standard() -> &'static Arc<InstructionSet> {
+        static STANDARD: OnceLock<Arc<InstructionSet>> = OnceLock::new();
+        STANDARD.get_or_init(|| {
+            let mut set = InstructionSet::new();
+            for instruction in standard_instructions() {
+                set.register(instruction).unwrap();
+            }
+            Arc::new(set)
+        })
+    }
+
+    // Fails if the opcode doesn't fit in two digits or is already taken, if the mnemonic is
+    // already taken in any case, or if there are more operands than a word has mode digits for.
+    pub fn register(&mut self, instruction: Instruction) -> Result<&mut Self, String> {
+        let code = instruction.code;
+        if !(1..100).contains(&code) {
+            return Err(format!("{} is not an opcode, must be in [1,99]", code));
+        }
+        if instruction.numFields() > MAX_OPERANDS {
+            return Err(format!(
+                "{} can't have {} operands, a word only has modes for {}",
+                instruction.mnemonic,
+                instruction.numFields(),
+                MAX_OPERANDS
+            ));
+        }
+        if let Some(taken) = &self.instructions[code as usize] {
+            return Err(format!(
+                "can't register {} as {}, it's already {}",
+                instruction.mnemonic, code, taken.mnemonic
+            ));
+        }
+        if let Some(taken) = self.find(instruction.mnemonic) {
+            return Err(format!(
+                "can't register {} as {}, {} is already {}",
+                instruction.mnemonic, code, taken.mnemonic, taken.code
+            ));
+        }
+        self.instructions[code as usize] = Some(Arc::new(instruction));
+        Ok(self)
+    }
+
+    // Both sets together, as long as they don't both have the same opcode.
+    pub fn with(mut self, other: InstructionSet) -> Result<Self, String> {
+        for instruction in other.instructions.into_iter().flatten() {
+            self.register((*instruction).clone())?;
+        }
+        Ok(self)
+    }
+
+    pub fn get(&self, code: i64) -> Option<&Instruction> {
+        match self.instructions.get(usize::try_from(code).ok()?) {
+            Some(Some(instruction)) => Some(instruction),
+            _ => None,
+        }
+    }
+
+    // the instruction with `mnemonic`, in any case, as the assembler looks them up
+    pub fn find(&self, mnemonic: &str) -> Option<&Instruction> {
+        let instructions = self.instructions.iter().flatten();
+        let mut found = instructions.filter(|i| i.mnemonic.eq_ignore_ascii_case(mnemonic));
+        found.next().map(|instruction| &**instruction)
+    }
+
+    pub fn decode(&self, cs: CurrentState) -> Result<OpCode, VmError> {
+        let data = cs.data;
+        let program_counter = cs.program_counter;
+        let relative_base = cs.relative_base;
+
+        if program_counter < 0 {
+            return Err(VmError::NegativeAddress {
+                address: program_counter,
+            });
+        }
+        let opcode = data.read(program_counter as usize);
+        let instruction = match self.instructions.get((opcode % 100) as usize) {
+            Some(Some(instruction)) if opcode >= 0 => instruction,
+            _ => {
+                return Err(VmError::InvalidOpCode {
+                    address: program_counter,
+                    opcode,
+                })
+            }
+        };
+
+        // an instruction only cares about the modes of the operands it has
+        let operand = |n: usize| {
+            let mode = (opcode / 10i64.pow(n as u32 + 2)) % 10;
+            let mode = Mode::try_from(mode).map_err(|_| VmError::InvalidMode {
+                address: program_counter,
+                mode,
+            })?;
+            Ok(Addr::from((
+                program_counter + 1 + n as i64,
+                mode,
+                relative_base,
+            )))
+        };
+        match &instruction.kind {
+            Kind::Standard(build) => build(&operand),
+            Kind::Custom(_) => Ok(OpCode::Custom {
+                instruction: instruction.clone(),
+                operands: (0..instruction.numFields())
+                    .map(operand)
+                    .collect::<Result<_, _>>()?,
+            }),
+        }
+    }
+}
+
+fn standard_instructions() -> Vec<Instruction> {
+    use OpCode::*;
+    vec![
+        Instruction::standard(1, "ADD", 2, true, |op| {
+            Ok(Add {
+                opAddr1: op(0)?,
+                opAddr2: op(1)?,
+                destAddr: op(2)?,
+            })
+        }),
+        Instruction::standard(2, "MUL", 2, true, |op| {
+            Ok(Mult {
+                opAddr1: op(0)?,
+                opAddr2: op(1)?,
+                destAddr: op(2)?,
+            })
+        }),
+        Instruction::standard(3, "IN", 0, true, |op| Ok(Read { destAddr: op(0)? })),
+        Instruction::standard(4, "OUT", 1, false, |op| Ok(Write { opAddr1: op(0)? })),
+        Instruction::standard(5, "JNZ", 2, false, |op| {
+            Ok(JumpIf {
+                boolAddr: op(0)?,
+                jumpAddr: op(1)?,
+            })
+        }),
+        Instruction::standard(6, "JZ", 2, false, |op| {
+            Ok(JumpIfNot {
+                boolAddr: op(0)?,
+                jumpAddr: op(1)?,
+            })
+        }),
+        Instruction::standard(7, "LT", 2, true, |op| {
+            Ok(SetIfLt {
+                opAddr1: op(0)?,
+                opAddr2: op(1)?,
+                destAddr: op(2)?,
+            })
+        }),
+        Instruction::standard(8, "EQ", 2, true, |op| {
+            Ok(SetIfEq {
+                opAddr1: op(0)?,
+                opAddr2: op(1)?,
+                destAddr: op(2)?,
+            })
+        }),
+        Instruction::standard(9, "ARB", 1, false, |op| {
+            Ok(SetRelBase {
+                relAddrChange: op(0)?,
+            })
+        }),
+        Instruction::standard(99, "HLT", 0, false, |_| Ok(Halt)),
+    ]
+}
+
+// Experimental instructions, to be added to the standard set with `with`.
+
+// AND (20) and OR (21): bitwise, like ADD
+pub fn bitwise() -> InstructionSet {
+    let mut set = InstructionSet::new();
+    set.register(Instruction::new(20, "AND", 2, true, |v| {
+        Some(Effect {
+            write: Some(v[0] & v[1]),
+            ..Effect::default()
+        })
+    }))
+    .unwrap()
+    .register(Instruction::new(21, "OR", 2, true, |v| {
+        Some(Effect {
+            write: Some(v[0] | v[1]),
+            ..Effect::default()
+        })
+    }))
+    .unwrap();
+    set
+}
+
+// DIV (22) and MOD (23), rounding towards zero. Dividing by zero is a fault.
+pub fn divmod() -> InstructionSet {
+    let mut set = InstructionSet::new();
+    set.register(Instruction::new(22, "DIV", 2, true, |v| {
+        Some(Effect {
+            write: Some(v[0].checked_div(v[1])?),
+            ..Effect::default()
+        })
+    }))
+    .unwrap()
+    .register(Instruction::new(23, "MOD", 2, true, |v| {
+        Some(Effect {
+            write: Some(v[0].checked_rem(v[1])?),
+            ..Effect::default()
+        })
+    }))
+    .unwrap();
+    set
+}
+
+// DBG (98): print the operand to stderr and carry on
+pub fn debug_trap() -> InstructionSet {
+    let mut set = InstructionSet::new();
+    set.register(Instruction::new(98, "DBG", 1, false, |v| {
+        eprintln!("DBG {}", v[0]);
+        Some(Effect::default())
+    }))
+    .unwrap();
+    set
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, Status};
+
+    // run until halted or faulted, returning the outputs along the way
+    fn run(program: &[i64], instructions: InstructionSet) -> (Vec<i64>, Result<(), VmError>) {
+        let mut machine = Machine::new(program.to_vec());
+        machine.set_instructions(instructions);
+        let mut output = Vec::new();
+        loop {
+            match machine.run_until() {
+                Ok(Status::Output(o)) => output.push(o),
+                Ok(_) => return (output, Ok(())),
+                Err(e) => return (output, Err(e)),
+            }
+        }
+    }
+
+    #[test]
+    fn standard_set() {
+        let standard = InstructionSet::standard();
+        let mnemonics: Vec<&str> = (0..100)
+            .filter_map(|code| standard.get(code))
+            .map(|instruction| instruction.mnemonic)
+            .collect();
+        assert_eq!(
+            mnemonics,
+            vec!["ADD", "MUL", "IN", "OUT", "JNZ", "JZ", "LT", "EQ", "ARB", "HLT"]
+        );
+        assert_eq!(standard.get(3).unwrap().numFields(), 1);
+        assert!(standard.get(20).is_none());
+    }
+
+    #[test]
+    fn custom_sets_coexist() {
+        let program = vec![
+            1120, 12, 10, 100, 4, 100, // AND #12, #10 -> [100]; OUT [100]
+            1121, 12, 3, 100, 4, 100, // OR
+            1122, 17, 5, 100, 4, 100, // DIV
+            1123, -17, 5, 100, 4, 100, // MOD
+            1122, 1, 0, 100, 99, // DIV by zero
+        ];
+        let extended = InstructionSet::standard()
+            .with(bitwise())
+            .and_then(|set| set.with(divmod()))
+            .and_then(|set| set.with(debug_trap()))
+            .unwrap();
+        let (output, result) = run(&program, extended);
+        assert_eq!(output, vec![8, 15, 3, -2]);
+        assert_eq!(
+            result,
+            Err(VmError::InstructionFault {
+                address: 24,
+                opcode: 22
+            })
+        );
+
+        // the same opcode meaning something else in another set
+        let mut xor = InstructionSet::standard();
+        xor.register(Instruction::new(20, "XOR", 2, true, |v| {
+            Some(Effect {
+                write: Some(v[0] ^ v[1]),
+                ..Effect::default()
+            })
+        }))
+        .unwrap();
+        let (output, result) = run(&program, xor);
+        assert_eq!(output, vec![6]);
+        assert_eq!(
+            result,
+            Err(VmError::InvalidOpCode {
+                address: 6,
+                opcode: 1121
+            })
+        );
+
+        let (output, result) = run(&program, InstructionSet::standard());
+        assert!(output.is_empty());
+        assert_eq!(
+            result,
+            Err(VmError::InvalidOpCode {
+                address: 0,
+                opcode: 1120
+            })
+        );
+    }
+
+    #[test]
+    fn conflicts() {
+        assert!(InstructionSet::standard()
+            .with(InstructionSet::standard())
+            .is_err());
+        let mut set = InstructionSet::new();
+        let nop = |code| Instruction::new(code, "NOP", 0, false, |_| Some(Effect::default()));
+        assert!(set.register(nop(100)).is_err());
+        assert!(set.register(nop(0)).is_err());
+        assert!(set.register(nop(50)).is_ok());
+        assert!(set.register(nop(50)).is_err());
+        // the assembler couldn't tell two NOPs apart
+        assert_eq!(
+            set.register(nop(51)).err(),
+            Some(String::from("can't register NOP as 51, NOP is already 50"))
+        );
+        let lower = Instruction::new(52, "nop", 0, false, |_| Some(Effect::default()));
+        assert!(set.register(lower).is_err());
+    }
+
+    #[test]
+    fn operands_need_mode_digits() {
+        let wide = |code, sources| {
+            Instruction::new(code, "SUM", sources, true, |values| {
+                Some(Effect {
+                    write: Some(values.iter().sum()),
+                    ..Effect::default()
+                })
+            })
+        };
+        let mut set = InstructionSet::new();
+        assert_eq!(
+            set.register(wide(10, 17)).err(),
+            Some(String::from(
+                "SUM can't have 18 operands, a word only has modes for 17"
+            ))
+        );
+        // the last operand's mode is the word's highest digit
+        set.register(wide(10, 16)).unwrap();
+        let mut program = vec![10 + 2 * 10i64.pow(18)];
+        program.extend(1..=16);
+        program.push(0);
+        let mut machine = Machine::new(program);
+        machine.set_instructions(set);
+        assert_eq!(machine.step(), Ok(Some(Status::Halted)));
+        assert_eq!(machine.data()[0], (1..=16).sum::<i64>());
+    }
+
+    #[test]
+    fn custom_output_and_jump() {
+        // SQR outputs the square of its operand, GO jumps to it
+        let mut set = InstructionSet::standard();
+        set.register(Instruction::new(30, "SQR", 1, false, |v| {
+            Some(Effect {
+                output: Some(v[0] * v[0]),
+                ..Effect::default()
+            })
+        }))
+        .unwrap()
+        .register(Instruction::new(31, "GO", 1, false, |v| {
+            Some(Effect {
+                jump: Some(v[0]),
+                ..Effect::default()
+            })
+        }))
+        .unwrap();
+        let program = vec![130, 7, 131, 5, 99, 130, 12, 99];
+        assert_eq!(run(&program, set), (vec![49, 144], Ok(())));
+    }
+}
@@ -0,0 +1,169 @@
+#![allow(non_snake_case)]
+
+mod assemble;
+mod cfg;
+mod debugger;
+mod decode;
+mod disassemble;
+mod error;
+mod fuzz;
+mod io;
+mod isa;
+mod machine;
+mod memdiff;
+mod memory;
+mod network;
+mod opcode;
+mod overflow;
+mod profile;
+mod program;
+mod router;
+mod snapshot;
+mod symbolic;
+mod trace;
+pub use assemble::*;
+pub use cfg::*;
+pub use debugger::*;
+pub use decode::*;
+pub use disassemble::*;
+pub use error::*;
+pub use fuzz::*;
+pub use io::*;
+pub use isa::*;
+pub use machine::*;
+pub use memdiff::*;
+pub use memory::*;
+pub use network::*;
+pub use opcode::*;
+pub use overflow::*;
+pub use profile::*;
+pub use program::*;
+pub use router::*;
+pub use snapshot::*;
+pub use symbolic::*;
+pub use trace::*;
+
+pub fn run_program(input: Vec<i64>, data: Vec<i64>) -> Result<Vec<i64>, VmError> {
+    run_program_with_limits(input, data, Limits::default())
+}
+
+pub fn run_program_with_limits(
+    input: Vec<i64>,
+    data: Vec<i64>,
+    limits: Limits,
+) -> Result<Vec<i64>, VmError> {
+    let mut machine = Machine::new(data);
+    machine.set_limits(limits);
+    input.into_iter().for_each(|i| machine.push_input(i));
+    let mut output = Vec::new();
+
+    loop {
+        match machine.run_until()? {
+            Status::Output(o) => output.push(o),
+            Status::Halted => break,
+            Status::NeedsInput => {
+                return Err(VmError::InputExhausted {
+                    address: machine.program_counter(),
+                })
+            }
+            Status::OutOfFuel { address, steps } => {
+                return Err(VmError::OutOfFuel { address, steps })
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn invalid_opcode() {
+        let error = run_program(vec![], vec![1101, 1, 1, 5, 42, 0]).unwrap_err();
+        assert_eq!(
+            error,
+            VmError::InvalidOpCode {
+                address: 4,
+                opcode: 42
+            }
+        );
+    }
+    #[test]
+    fn invalid_mode() {
+        let error = run_program(vec![], vec![1301, 1, 1, 5, 99, 0]).unwrap_err();
+        assert_eq!(
+            error,
+            VmError::InvalidMode {
+                address: 0,
+                mode: 3
+            }
+        );
+    }
+    #[test]
+    fn write_to_immediate() {
+        let error = run_program(vec![], vec![11101, 1, 1, 5, 99, 0]).unwrap_err();
+        assert_eq!(error, VmError::WriteToImmediate { address: 3 });
+    }
+    #[test]
+    fn negative_address() {
+        let error = run_program(vec![], vec![204, -7, 99]).unwrap_err();
+        assert_eq!(error, VmError::NegativeAddress { address: -7 });
+        let error = run_program(vec![], vec![1105, 1, -2, 99]).unwrap_err();
+        assert_eq!(error, VmError::NegativeAddress { address: -2 });
+    }
+    #[test]
+    fn address_overflow() {
+        let error = run_program(vec![], vec![109, i64::MAX, 109, 1, 99]).unwrap_err();
+        assert_eq!(error, VmError::AddressOverflow { address: 3 });
+        let error = run_program(vec![], vec![109, i64::MAX, 204, 1, 99]).unwrap_err();
+        assert_eq!(error, VmError::AddressOverflow { address: 3 });
+        let error = run_program(vec![], vec![109, i64::MIN, 204, -1, 99]).unwrap_err();
+        assert_eq!(error, VmError::AddressOverflow { address: 3 });
+    }
+    #[test]
+    fn input_exhausted() {
+        let error = run_program(vec![1], vec![3, 9, 3, 9, 99]).unwrap_err();
+        assert_eq!(error, VmError::InputExhausted { address: 2 });
+    }
+    #[test]
+    fn paged_memory_high_addresses() {
+        // copy 42 out to address 10^12 and back, then print it
+        let high = 1000000000000;
+        let data = vec![
+            21101, 0, 42, 0, 109, high, 21001, 0, 0, 0, 1201, 0, 0, 100, 4, 100, 99,
+        ];
+        let mut machine = Machine::with_memory(PagedMemory::from(data));
+        assert_eq!(machine.run_until().unwrap(), Status::Output(42));
+        assert_eq!(machine.run_until().unwrap(), Status::Halted);
+        assert_eq!(machine.data().read(high as usize), 42);
+        assert_eq!(machine.data().num_pages(), 2);
+    }
+    #[test]
+    fn reads_do_not_allocate() {
+        // output what's at 10^12, which was never written
+        let high = 1000000000000;
+        let mut machine = Machine::with_memory(PagedMemory::from(vec![4, high, 99]));
+        assert_eq!(machine.run_until().unwrap(), Status::Output(0));
+        assert_eq!(machine.run_until().unwrap(), Status::Halted);
+        assert_eq!(machine.data().num_pages(), 1);
+        assert_eq!(machine.data().size(), 3);
+    }
+    #[test]
+    fn out_of_fuel() {
+        let limits = Limits {
+            fuel: Some(100),
+            ..Limits::default()
+        };
+        let error = run_program_with_limits(vec![], vec![1105, 1, 0], limits).unwrap_err();
+        assert_eq!(
+            error,
+            VmError::OutOfFuel {
+                address: 0,
+                steps: 100
+            }
+        );
+        let output = run_program_with_limits(vec![], vec![104, 7, 99], limits).unwrap();
+        assert_eq!(output, vec![7]);
+    }
+}
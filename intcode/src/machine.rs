@@ -0,0 +1,436 @@
+use crate::opcode::*;
+use crate::{
+    DecodeCache, InstructionSet, Memory, Overflow, PagedMemory, Profile, Promoted, Trace,
+    TraceEntry, VmError,
+};
+use std::collections::VecDeque;
+use std::iter;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    NeedsInput,
+    Output(i64),
+    Halted,
+    // stopped by its `Limits` before the instruction at `address`
+    OutOfFuel { address: i64, steps: usize },
+}
+use Status::*;
+
+// How long a machine may run before it stops with `OutOfFuel`. Unlimited by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    // instructions to execute
+    pub fuel: Option<usize>,
+    // wall-clock time, checked every `CLOCK_INTERVAL` instructions
+    pub time: Option<Duration>,
+}
+
+pub const CLOCK_INTERVAL: usize = 1024;
+
+// A paused intcode program. Unlike `run_program`, a `Machine` never consumes input it doesn't
+// have: a `Read` with an empty input queue leaves the program counter where it is and reports
+// `NeedsInput`, so the caller can `push_input` and carry on from the same instruction.
+#[derive(Debug, Clone)]
+pub struct Machine<M: Memory = Vec<i64>> {
+    data: M,
+    program_counter: i64,
+    relative_base: i64,
+    input: VecDeque<i64>,
+    steps: usize,
+    trace: Option<Trace>,
+    profile: Option<Profile>,
+    fuel: Option<usize>,
+    deadline: Option<Instant>,
+    decoded: Option<DecodeCache>,
+    instructions: Arc<InstructionSet>,
+    overflow: Overflow,
+    promoted: Promoted,
+}
+impl Machine {
+    pub fn new(data: Vec<i64>) -> Self {
+        Machine::with_memory(data)
+    }
+}
+impl Machine<PagedMemory> {
+    // A machine whose forks share memory until they write to it.
+    pub fn paged(data: Vec<i64>) -> Self {
+        Machine::with_memory(PagedMemory::from(data))
+    }
+}
+impl<M: Memory> Machine<M> {
+    pub fn with_memory(data: M) -> Self {
+        Machine::resume(data, 0, 0, VecDeque::new(), 0)
+    }
+
+    // pick a machine up from where it was paused
+    pub(crate) fn resume(
+        data: M,
+        program_counter: i64,
+        relative_base: i64,
+        input: VecDeque<i64>,
+        steps: usize,
+    ) -> Self {
+        Machine {
+            data,
+            program_counter,
+            relative_base,
+            input,
+            steps,
+            trace: None,
+            profile: None,
+            fuel: None,
+            deadline: None,
+            decoded: Some(DecodeCache::new()),
+            instructions: InstructionSet::shared_standard().clone(),
+            overflow: Overflow::default(),
+            promoted: Promoted::new(),
+        }
+    }
+
+    pub fn data(&self) -> &M {
+        &self.data
+    }
+
+    pub fn program_counter(&self) -> i64 {
+        self.program_counter
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    // number of instructions executed so far
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    // Record every instruction executed from now on.
+    pub fn start_trace(&mut self) {
+        self.trace = Some(Trace::new());
+    }
+
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        self.trace.take()
+    }
+
+    // Start counting down `limits` from now, replacing any set before.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.fuel = limits.fuel;
+        self.deadline = limits.time.map(|time| Instant::now() + time);
+    }
+
+    // instructions left to execute, if limited
+    pub fn fuel(&self) -> Option<usize> {
+        self.fuel
+    }
+
+    fn out_of_fuel(&self) -> bool {
+        let timed_out =
+            |deadline| self.steps.is_multiple_of(CLOCK_INTERVAL) && Instant::now() >= deadline;
+        self.fuel == Some(0) || self.deadline.is_some_and(timed_out)
+    }
+
+    // Decode with `instructions` from now on instead of the standard set. An `Arc` lets many
+    // machines share one set.
+    pub fn set_instructions(&mut self, instructions: impl Into<Arc<InstructionSet>>) {
+        self.instructions = instructions.into();
+    }
+
+    pub fn instructions(&self) -> &InstructionSet {
+        &self.instructions
+    }
+
+    // What ADD and MUL do with results too big for a word. Trap by default.
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    // the results `Overflow::Promote` has kept wider than a word, by address
+    pub fn promoted(&self) -> &Promoted {
+        &self.promoted
+    }
+
+    pub(crate) fn set_promoted(&mut self, promoted: Promoted) {
+        self.promoted = promoted;
+    }
+
+    // Instructions are decoded once per address and reused until their opcode changes, unless
+    // the cache is taken away. A cache taken from one machine can be given to another.
+    pub fn set_decode_cache(&mut self, cache: Option<DecodeCache>) {
+        self.decoded = cache;
+    }
+
+    pub fn decode_cache(&self) -> Option<&DecodeCache> {
+        self.decoded.as_ref()
+    }
+
+    pub fn take_decode_cache(&mut self) -> Option<DecodeCache> {
+        self.decoded.take()
+    }
+
+    // Count every instruction executed from now on.
+    pub fn start_profile(&mut self) {
+        self.profile = Some(Profile::new());
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    // input pushed but not read yet
+    pub fn pending_input(&self) -> &VecDeque<i64> {
+        &self.input
+    }
+
+    // An independent copy of the machine as it is now, limits included but without the trace or
+    // profile. With `PagedMemory` the copy costs a pointer per page, and pages are only copied
+    // when one side writes to them.
+    pub fn fork(&self) -> Self
+    where
+        M: Clone,
+    {
+        let mut fork = Machine::resume(
+            self.data.clone(),
+            self.program_counter,
+            self.relative_base,
+            self.input.clone(),
+            self.steps,
+        );
+        fork.fuel = self.fuel;
+        fork.deadline = self.deadline;
+        fork.instructions = self.instructions.clone();
+        fork.overflow = self.overflow;
+        fork.promoted = self.promoted.clone();
+        if self.decoded.is_none() {
+            fork.decoded = None;
+        }
+        fork
+    }
+
+    pub fn push_input(&mut self, i: i64) {
+        self.input.push_back(i);
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.program_counter < 0 || self.program_counter as usize >= self.data.size()
+    }
+
+    // Decode the instruction at the program counter without executing it.
+    pub fn current(&self) -> Result<OpCode, VmError> {
+        self.instructions.decode(CurrentState {
+            data: &self.data,
+            program_counter: self.program_counter,
+            relative_base: self.relative_base,
+        })
+    }
+
+    // Execute a single instruction. Returns `None` if the instruction ran without anything the
+    // caller needs to react to.
+    pub fn step(&mut self) -> Result<Option<Status>, VmError> {
+        if self.is_halted() {
+            return Ok(Some(Halted));
+        }
+
+        if self.out_of_fuel() {
+            return Ok(Some(OutOfFuel {
+                address: self.program_counter,
+                steps: self.steps,
+            }));
+        }
+
+        // an instruction can't be more than a word
+        if self.promoted.contains_key(&(self.program_counter as usize)) {
+            return Err(VmError::Overflow {
+                address: self.program_counter,
+            });
+        }
+        let opcode = match self.decoded.as_mut() {
+            Some(cache) => cache.decode(
+                &self.instructions,
+                &self.data,
+                self.program_counter,
+                self.relative_base,
+            )?,
+            None => self.current()?,
+        };
+        if let OpCode::Read { .. } = opcode {
+            if self.input.is_empty() {
+                return Ok(Some(NeedsInput));
+            }
+        }
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record(&opcode, &self.data, self.program_counter);
+        }
+        let traced = self.trace.as_ref().map(|_| {
+            TraceEntry::before(
+                self.steps,
+                opcode.clone(),
+                &self.data,
+                self.program_counter,
+                self.relative_base,
+                self.input.front().cloned(),
+            )
+        });
+
+        let input = &mut self.input;
+        let output = opcode.executeIntruction(
+            &mut self.relative_base,
+            &mut self.data,
+            &mut self.program_counter,
+            &mut iter::from_fn(|| input.pop_front()),
+            self.overflow,
+            &mut self.promoted,
+        )?;
+        self.steps += 1;
+        if let Some(fuel) = self.fuel.as_mut() {
+            *fuel -= 1;
+        }
+        if let (Some(trace), Some(entry)) = (self.trace.as_mut(), traced) {
+            trace.push(entry.after(&self.data, self.relative_base, output));
+        }
+
+        Ok(match output {
+            Some(o) => Some(Output(o)),
+            None if self.is_halted() => Some(Halted),
+            None => None,
+        })
+    }
+
+    // Run until the program produces output, blocks on input, halts or runs out of fuel.
+    pub fn run_until(&mut self) -> Result<Status, VmError> {
+        loop {
+            if let Some(status) = self.step()? {
+                return Ok(status);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PAGE_SIZE;
+
+    #[test]
+    fn blocks_on_input() {
+        // read two numbers, write their sum
+        let mut machine = Machine::new(vec![3, 11, 3, 12, 1, 11, 12, 13, 4, 13, 99, 0, 0, 0]);
+        assert_eq!(machine.run_until().unwrap(), NeedsInput);
+        assert_eq!(machine.run_until().unwrap(), NeedsInput);
+        assert_eq!(machine.program_counter(), 0);
+
+        machine.push_input(40);
+        assert_eq!(machine.run_until().unwrap(), NeedsInput);
+        assert_eq!(machine.program_counter(), 2);
+
+        machine.push_input(2);
+        assert_eq!(machine.run_until().unwrap(), Output(42));
+        assert_eq!(machine.run_until().unwrap(), Halted);
+        assert_eq!(machine.run_until().unwrap(), Halted);
+    }
+
+    #[test]
+    fn echo_loop() {
+        // read a number, write it, jump back to the start
+        let mut machine = Machine::new(vec![3, 100, 4, 100, 1105, 1, 0]);
+        for i in 0..5 {
+            assert_eq!(machine.run_until().unwrap(), NeedsInput);
+            machine.push_input(i);
+            assert_eq!(machine.run_until().unwrap(), Output(i));
+        }
+        assert_eq!(machine.run_until().unwrap(), NeedsInput);
+    }
+
+    #[test]
+    fn step_reports_nothing_for_plain_instructions() {
+        let mut machine = Machine::new(vec![1101, 1, 1, 5, 99, 0]);
+        assert_eq!(machine.step().unwrap(), None);
+        assert_eq!(machine.data().read(5), 2);
+        assert_eq!(machine.step().unwrap(), Some(Halted));
+        assert!(machine.is_halted());
+    }
+
+    #[test]
+    fn forks_run_independently() {
+        // read a number and output it doubled, forever
+        let mut machine = Machine::paged(vec![3, 100, 1002, 100, 2, 100, 4, 100, 1105, 1, 0]);
+        machine.start_trace();
+        machine.push_input(1);
+        assert_eq!(machine.run_until().unwrap(), Output(2));
+
+        let mut fork = machine.fork();
+        assert!(fork.trace().is_none());
+        assert_eq!(fork.steps(), machine.steps());
+        fork.push_input(10);
+        machine.push_input(20);
+        assert_eq!(fork.run_until().unwrap(), Output(20));
+        assert_eq!(machine.run_until().unwrap(), Output(40));
+        assert_eq!(fork.data().read(100), 20);
+        assert_eq!(machine.data().read(100), 40);
+    }
+
+    #[test]
+    fn forks_share_pages_they_only_read() {
+        // output words from the second and third pages
+        let mut data = vec![4, 5000, 4, 9000, 99];
+        data.resize(3 * PAGE_SIZE, 7);
+        let machine = Machine::paged(data);
+        let mut fork = machine.fork();
+        assert_eq!(fork.run_until().unwrap(), Output(7));
+        assert_eq!(fork.run_until().unwrap(), Output(7));
+        assert_eq!(fork.run_until().unwrap(), Halted);
+        assert_eq!(fork.data().shared_pages(machine.data()), 3);
+    }
+
+    #[test]
+    fn runs_out_of_fuel() {
+        // loop forever
+        let mut machine = Machine::new(vec![1105, 1, 0]);
+        machine.set_limits(Limits {
+            fuel: Some(10),
+            time: None,
+        });
+        assert_eq!(
+            machine.run_until().unwrap(),
+            OutOfFuel {
+                address: 0,
+                steps: 10
+            }
+        );
+        assert_eq!(machine.fuel(), Some(0));
+
+        machine.set_limits(Limits {
+            fuel: Some(1),
+            ..Limits::default()
+        });
+        assert_eq!(machine.step().unwrap(), None);
+        assert_eq!(machine.steps(), 11);
+    }
+
+    #[test]
+    fn runs_out_of_time() {
+        let mut machine = Machine::new(vec![1105, 1, 0]);
+        machine.set_limits(Limits {
+            fuel: None,
+            time: Some(Duration::from_millis(20)),
+        });
+        match machine.run_until().unwrap() {
+            OutOfFuel { steps, .. } => assert_eq!(steps % CLOCK_INTERVAL, 0),
+            status => panic!("expected to run out of time, got {:?}", status),
+        }
+    }
+}
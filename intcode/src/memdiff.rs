@@ -0,0 +1,283 @@
+use crate::{disassemble, Line, Memory};
+use colored::*;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Range;
+
+const MAX_HEATMAP_LINES: usize = 32;
+const SHOWN_WORDS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    // part of an instruction reachable from address 0, as the memory was before
+    Code,
+    Data,
+}
+
+// A run of neighbouring cells that changed, all in the same kind of region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub start: usize,
+    pub before: Vec<i64>,
+    pub after: Vec<i64>,
+    pub region: Region,
+}
+impl Change {
+    pub fn len(&self) -> usize {
+        self.after.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.after.is_empty()
+    }
+}
+
+// What changed between two images of a machine's memory, e.g. before and after a run.
+pub struct MemoryDiff {
+    changes: Vec<Change>,
+    code: BTreeSet<usize>,
+    size: usize,
+}
+impl MemoryDiff {
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn changed(&self, address: usize) -> bool {
+        let after = self.changes.partition_point(|c| c.start <= address);
+        after > 0 && address < self.changes[after - 1].start + self.changes[after - 1].len()
+    }
+
+    // How many cells changed in each kind of region.
+    pub fn count(&self, region: Region) -> usize {
+        let changes = self.changes.iter().filter(|c| c.region == region);
+        changes.map(|c| c.len()).sum()
+    }
+
+    // how many of the cells in `cells` changed
+    fn count_changed(&self, cells: Range<usize>) -> usize {
+        let first = self
+            .changes
+            .partition_point(|c| c.start + c.len() <= cells.start);
+        self.changes[first..]
+            .iter()
+            .take_while(|c| c.start < cells.end)
+            .map(|c| (c.start + c.len()).min(cells.end) - c.start.max(cells.start))
+            .sum()
+    }
+
+    // Memory drawn `width` characters to a line (at least 1), each standing for an equal share
+    // of the cells so that it all fits in a screenful: the more of them changed the denser the
+    // block, red where code changed and yellow where only data did.
+    pub fn heatmap(&self, width: usize) -> String {
+        let width = width.max(1);
+        let cells_per_char = self
+            .size
+            .div_ceil(width.saturating_mul(MAX_HEATMAP_LINES))
+            .max(1);
+        let cells_per_line = width.saturating_mul(cells_per_char);
+        let mut s = format!("one character = {} cell(s)\n", cells_per_char);
+        for line_start in (0..self.size).step_by(cells_per_line) {
+            let line_end = line_start.saturating_add(cells_per_line).min(self.size);
+            let chars: String = (line_start..line_end)
+                .step_by(cells_per_char)
+                .map(|start| {
+                    let cells = start..start.saturating_add(cells_per_char).min(self.size);
+                    let total = cells.len();
+                    let shade = match self.count_changed(cells.clone()) {
+                        0 => return ".".dimmed().to_string(),
+                        n if 3 * n <= total => "░",
+                        n if 3 * n <= 2 * total => "▒",
+                        _ => "█",
+                    };
+                    match self.code.range(cells).any(|&a| self.changed(a)) {
+                        true => shade.red().to_string(),
+                        false => shade.yellow().to_string(),
+                    }
+                })
+                .collect();
+            s.push_str(&format!("{:>6}: {}\n", line_start, chars));
+        }
+        s
+    }
+}
+// one line per change, e.g. `    63..64  data  1,0 -> 0,1`
+impl fmt::Display for MemoryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words = |words: &[i64]| {
+            let shown: Vec<String> = words
+                .iter()
+                .take(SHOWN_WORDS)
+                .map(|w| w.to_string())
+                .collect();
+            match words.len() > SHOWN_WORDS {
+                true => format!("{},...", shown.join(",")),
+                false => shown.join(","),
+            }
+        };
+        for change in &self.changes {
+            let range = match change.len() {
+                1 => change.start.to_string(),
+                n => format!("{}..{}", change.start, change.start + n),
+            };
+            let region = match change.region {
+                Region::Code => "code",
+                Region::Data => "data",
+            };
+            writeln!(
+                f,
+                "{:>12}  {}  {} -> {}",
+                range,
+                region,
+                words(&change.before),
+                words(&change.after)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn diff_memory(before: &dyn Memory, after: &dyn Memory) -> MemoryDiff {
+    let code: BTreeSet<usize> = disassemble(before)
+        .lines()
+        .iter()
+        .filter_map(|line| match line {
+            Line::Code { address, opcode } => {
+                Some(*address..=address + opcode.numFields() as usize)
+            }
+            Line::Data { .. } => None,
+        })
+        .flatten()
+        .collect();
+    let region = |address: usize| match code.contains(&address) {
+        true => Region::Code,
+        false => Region::Data,
+    };
+
+    // only cells populated on one side or the other can differ
+    let mut populated = before.populated();
+    populated.extend(after.populated());
+    populated.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in populated {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    let size = before.size().max(after.size());
+    let mut changes: Vec<Change> = Vec::new();
+    for address in merged.into_iter().flatten() {
+        let (old, new) = (before.read(address), after.read(address));
+        if old == new {
+            continue;
+        }
+        match changes.last_mut() {
+            Some(last) if last.start + last.len() == address && last.region == region(address) => {
+                last.before.push(old);
+                last.after.push(new);
+            }
+            _ => changes.push(Change {
+                start: address,
+                before: vec![old],
+                after: vec![new],
+                region: region(address),
+            }),
+        }
+    }
+    MemoryDiff {
+        changes,
+        code,
+        size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble, Machine, PagedMemory};
+
+    #[test]
+    fn groups_changes_into_code_and_data() {
+        // patches its own output operand, then fills in a table after the code
+        let program = assemble(
+            "
+                    ADD #3, #4 -> [13]
+                    ADD #1, #0 -> [table]
+                    ADD #2, #0 -> [16]
+                    OUT #0
+                    HLT
+            table:  .data 0, 0, 0
+            ",
+        )
+        .unwrap();
+        let before = program.clone();
+        let mut machine = Machine::new(program);
+        machine.run_until().unwrap();
+
+        let diff = diff_memory(&before, machine.data());
+        assert_eq!(
+            diff.changes(),
+            &[
+                Change {
+                    start: 13,
+                    before: vec![0],
+                    after: vec![7],
+                    region: Region::Code,
+                },
+                Change {
+                    start: 15,
+                    before: vec![0, 0],
+                    after: vec![1, 2],
+                    region: Region::Data,
+                },
+            ]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "          13  code  0 -> 7\n      15..17  data  0,0 -> 1,2\n"
+        );
+        assert!(diff.changed(16) && !diff.changed(17));
+    }
+
+    #[test]
+    fn heatmap() {
+        let before = vec![0; 300];
+        let mut after = before.clone();
+        after[10..20].iter_mut().for_each(|w| *w = 1);
+        let diff = diff_memory(&before, &after);
+        assert_eq!(diff.changes().len(), 1);
+        assert_eq!(diff.changes()[0].len(), 10);
+        assert_eq!(
+            diff.to_string(),
+            "      10..20  data  0,0,0,0,0,0,0,0,... -> 1,1,1,1,1,1,1,1,...\n"
+        );
+
+        // 300 cells in at most 32 lines of 4 characters is 3 cells to a character
+        let heatmap = diff.heatmap(4);
+        assert!(heatmap.starts_with("one character = 3 cell(s)\n"));
+        assert_eq!(heatmap.lines().count(), 1 + 25);
+        assert_eq!(heatmap.matches('█').count(), 2);
+        assert_eq!(heatmap.matches('▒').count(), 2);
+    }
+
+    #[test]
+    fn sparse_memory() {
+        let mut before = PagedMemory::from(vec![99]);
+        *before.cell(1 << 40) = 1;
+        let mut after = before.clone();
+        *after.cell(1 << 40) = 2;
+        *after.cell((1 << 50) + 1) = 3;
+
+        let diff = diff_memory(&before, &after);
+        assert_eq!(
+            diff.to_string(),
+            "1099511627776  data  1 -> 2\n1125899906842625  data  0 -> 3\n"
+        );
+        // a width of 0 draws one character to a line
+        let heatmap = diff.heatmap(0);
+        assert_eq!(heatmap.lines().count(), 1 + 32);
+        assert_eq!(heatmap.matches('░').count(), 2);
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+use std::sync::Arc;
+
+// Intcode memory: every non-negative address holds a value, and anything never written reads as
+// 0. `size` is one past the highest address loaded or written so far, which is where a program
+// counter runs off the end of the program; reading never changes it.
+pub trait Memory {
+    fn read(&self, address: usize) -> i64;
+    fn cell(&mut self, address: usize) -> &mut i64;
+    fn size(&self) -> usize;
+
+    // Make memory reach to `size` if it doesn't already, or return false if it can't be made to
+    // hold that much. Anything new reads as 0.
+    fn try_extend(&mut self, size: usize) -> bool {
+        if size > self.size() {
+            *self.cell(size - 1) = 0;
+        }
+        true
+    }
+
+    // The ranges of addresses that may hold something other than 0, in order and with gaps
+    // between them; everything else reads as 0. Walking these rather than every address up to
+    // `size` keeps sparse memory cheap to look through.
+    fn populated(&self) -> Vec<Range<usize>> {
+        let size = self.size();
+        (size > 0).then_some(0..size).into_iter().collect()
+    }
+}
+
+// Dense memory. Touching address n allocates every address below it.
+impl Memory for Vec<i64> {
+    fn read(&self, address: usize) -> i64 {
+        *self.get(address).unwrap_or(&0)
+    }
+
+    fn cell(&mut self, address: usize) -> &mut i64 {
+        if address >= self.len() {
+            self.resize(address + 1, 0);
+        }
+        &mut self[address]
+    }
+
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    fn try_extend(&mut self, size: usize) -> bool {
+        if size > self.len() {
+            if self.try_reserve_exact(size - self.len()).is_err() {
+                return false;
+            }
+            self.resize(size, 0);
+        }
+        true
+    }
+}
+
+pub const PAGE_SIZE: usize = 4096;
+
+// Sparse memory made of `PAGE_SIZE`-word pages, allocated only once something is written to
+// them, so scratch space at high addresses costs a page rather than everything below it.
+//
+// The pages a memory is loaded with are frozen and shared by all its clones, and each clone keeps
+// its own copy of just the pages it has written to since, so cloning costs a pointer per page
+// written rather than one per page.
+#[derive(Debug, Clone, Default)]
+pub struct PagedMemory {
+    base: Arc<HashMap<usize, Vec<i64>>>,
+    written: HashMap<usize, Arc<Vec<i64>>>,
+    size: usize,
+}
+impl PagedMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn num_pages(&self) -> usize {
+        let unwritten = self.base.keys().filter(|n| !self.written.contains_key(n));
+        self.written.len() + unwritten.count()
+    }
+
+    // how many pages are still shared with `other`, which this was cloned from or cloned into
+    pub fn shared_pages(&self, other: &PagedMemory) -> usize {
+        let written = self
+            .written
+            .iter()
+            .filter(|(n, page)| match other.written.get(n) {
+                Some(other) => Arc::ptr_eq(page, other),
+                None => false,
+            });
+        let unwritten = if Arc::ptr_eq(&self.base, &other.base) {
+            let unwritten = self
+                .base
+                .keys()
+                .filter(|n| !self.written.contains_key(n) && !other.written.contains_key(n));
+            unwritten.count()
+        } else {
+            0
+        };
+        written.count() + unwritten
+    }
+}
+impl From<Vec<i64>> for PagedMemory {
+    fn from(data: Vec<i64>) -> Self {
+        let mut base = HashMap::new();
+        for (page, chunk) in data.chunks(PAGE_SIZE).enumerate() {
+            let mut words = chunk.to_vec();
+            words.resize(PAGE_SIZE, 0);
+            base.insert(page, words);
+        }
+        PagedMemory {
+            base: Arc::new(base),
+            written: HashMap::new(),
+            size: data.len(),
+        }
+    }
+}
+impl Memory for PagedMemory {
+    fn read(&self, address: usize) -> i64 {
+        let n = address / PAGE_SIZE;
+        let page = match self.written.get(&n) {
+            Some(page) => Some(&**page),
+            None => self.base.get(&n),
+        };
+        match page {
+            Some(page) => page[address % PAGE_SIZE],
+            None => 0,
+        }
+    }
+
+    fn cell(&mut self, address: usize) -> &mut i64 {
+        if address >= self.size {
+            self.size = address + 1;
+        }
+        let n = address / PAGE_SIZE;
+        let base = &self.base;
+        let page = self.written.entry(n).or_insert_with(|| {
+            Arc::new(base.get(&n).cloned().unwrap_or_else(|| vec![0; PAGE_SIZE]))
+        });
+        &mut Arc::make_mut(page)[address % PAGE_SIZE]
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn populated(&self) -> Vec<Range<usize>> {
+        let pages: BTreeSet<usize> = self
+            .base
+            .keys()
+            .chain(self.written.keys())
+            .cloned()
+            .collect();
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for n in pages {
+            let page = n * PAGE_SIZE..((n + 1) * PAGE_SIZE).min(self.size);
+            match ranges.last_mut() {
+                Some(last) if last.end == page.start => last.end = page.end,
+                _ if page.is_empty() => (),
+                _ => ranges.push(page),
+            }
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paged_memory_matches_vec() {
+        let data: Vec<i64> = (0..10000).collect();
+        let mut paged = PagedMemory::from(data.clone());
+        assert_eq!(paged.size(), data.len());
+        assert_eq!(paged.num_pages(), 3);
+        for &address in &[0, 1, 4095, 4096, 9999, 10000, 123456] {
+            assert_eq!(paged.read(address), data.read(address));
+        }
+
+        *paged.cell(5000) = -1;
+        assert_eq!(paged.read(5000), -1);
+        assert_eq!(paged.size(), data.len());
+    }
+
+    #[test]
+    fn paged_memory_high_addresses() {
+        let mut paged = PagedMemory::new();
+        assert_eq!(paged.read(1_000_000_000_000), 0);
+        assert_eq!(paged.num_pages(), 0);
+
+        *paged.cell(1_000_000_000_000) = 7;
+        assert_eq!(paged.read(1_000_000_000_000), 7);
+        assert_eq!(paged.size(), 1_000_000_000_001);
+        assert_eq!(paged.num_pages(), 1);
+    }
+
+    #[test]
+    fn extends_only_as_far_as_it_can() {
+        let mut dense = vec![1, 2];
+        assert!(dense.try_extend(5));
+        assert_eq!(dense, vec![1, 2, 0, 0, 0]);
+        assert!(!dense.try_extend(1 << 62));
+        assert_eq!(dense.size(), 5);
+
+        let mut paged = PagedMemory::new();
+        assert!(paged.try_extend(1 << 62));
+        assert_eq!(paged.size(), 1 << 62);
+        assert_eq!(paged.num_pages(), 1);
+    }
+
+    #[test]
+    fn populated_ranges() {
+        assert!(Vec::<i64>::new().populated().is_empty());
+        assert_eq!(vec![1, 2, 3].populated().pop(), Some(0..3));
+
+        let mut paged = PagedMemory::from(vec![1; PAGE_SIZE + 10]);
+        assert_eq!(paged.populated().pop(), Some(0..PAGE_SIZE + 10));
+        *paged.cell(3 * PAGE_SIZE) = 1;
+        *paged.cell(1_000_000_000_000) = 7;
+        let high = 1_000_000_000_000 / PAGE_SIZE * PAGE_SIZE;
+        assert_eq!(
+            paged.populated(),
+            vec![
+                0..2 * PAGE_SIZE,
+                3 * PAGE_SIZE..4 * PAGE_SIZE,
+                high..1_000_000_000_001
+            ]
+        );
+    }
+
+    #[test]
+    fn clones_copy_only_written_pages() {
+        let original = PagedMemory::from(vec![1; 4 * PAGE_SIZE]);
+        let mut clone = original.clone();
+        assert_eq!(clone.shared_pages(&original), 4);
+
+        *clone.cell(PAGE_SIZE + 1) = 2;
+        *clone.cell(PAGE_SIZE + 2) = 3;
+        assert_eq!(clone.shared_pages(&original), 3);
+        assert_eq!(original.read(PAGE_SIZE + 1), 1);
+        assert_eq!(clone.read(PAGE_SIZE + 1), 2);
+
+        // a page only the clone has isn't shared
+        *clone.cell(10 * PAGE_SIZE) = 4;
+        assert_eq!(clone.shared_pages(&original), 3);
+        assert_eq!(original.read(10 * PAGE_SIZE), 0);
+    }
+}
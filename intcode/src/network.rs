@@ -0,0 +1,254 @@
+use crate::{Machine, Status, VmError};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    // every machine halted
+    Halted,
+    // the machines still running are all waiting for input nobody is going to send
+    Quiescent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkError {
+    // a node number that no node was added with
+    NoNode { node: usize },
+}
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::NoNode { node } => write!(f, "there is no node {}", node),
+        }
+    }
+}
+impl Error for NetworkError {}
+
+#[derive(Debug, Clone)]
+struct Node {
+    machine: Machine,
+    outputs: Vec<i64>,
+    targets: Vec<usize>,
+}
+
+// Machines wired output-to-input. Each output goes to every machine its node is connected to, in
+// the order the connections were made. Machines take turns in the order they were added, each
+// running until it blocks on input or halts, so a run is deterministic.
+#[derive(Debug, Clone, Default)]
+pub struct Network {
+    nodes: Vec<Node>,
+}
+impl Network {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Add a machine running its own copy of `program` and return its node number.
+    pub fn add_node(&mut self, program: &[i64]) -> usize {
+        self.add_machine(Machine::new(program.to_vec()))
+    }
+
+    pub fn add_machine(&mut self, machine: Machine) -> usize {
+        self.nodes.push(Node {
+            machine,
+            outputs: Vec::new(),
+            targets: Vec::new(),
+        });
+        self.nodes.len() - 1
+    }
+
+    // Every method taking a node number fails if no node was added with it.
+    fn node(&self, node: usize) -> Result<&Node, NetworkError> {
+        self.nodes.get(node).ok_or(NetworkError::NoNode { node })
+    }
+
+    fn node_mut(&mut self, node: usize) -> Result<&mut Node, NetworkError> {
+        self.nodes
+            .get_mut(node)
+            .ok_or(NetworkError::NoNode { node })
+    }
+
+    pub fn connect(&mut self, from: usize, to: usize) -> Result<(), NetworkError> {
+        self.node(to)?;
+        self.node_mut(from)?.targets.push(to);
+        Ok(())
+    }
+
+    pub fn push_input(&mut self, node: usize, i: i64) -> Result<(), NetworkError> {
+        self.node_mut(node)?.machine.push_input(i);
+        Ok(())
+    }
+
+    pub fn machine(&self, node: usize) -> Result<&Machine, NetworkError> {
+        Ok(&self.node(node)?.machine)
+    }
+
+    // everything `node` has output so far
+    pub fn outputs(&self, node: usize) -> Result<&Vec<i64>, NetworkError> {
+        Ok(&self.node(node)?.outputs)
+    }
+
+    // Run until every machine has halted or is stuck waiting for input. When a machine fails or
+    // runs out of fuel, whatever it output before then is still delivered and kept in `outputs`.
+    pub fn run(&mut self) -> Result<Outcome, VmError> {
+        loop {
+            let mut progress = false;
+            for n in 0..self.nodes.len() {
+                let node = &mut self.nodes[n];
+                let steps = node.machine.steps();
+                let mut sent = Vec::new();
+                let failed = loop {
+                    match node.machine.run_until() {
+                        Ok(Status::Output(o)) => sent.push(o),
+                        Ok(Status::OutOfFuel { address, steps }) => {
+                            break Some(VmError::OutOfFuel { address, steps })
+                        }
+                        Ok(Status::NeedsInput) | Ok(Status::Halted) => break None,
+                        Err(e) => break Some(e),
+                    }
+                };
+                progress |= node.machine.steps() != steps;
+
+                node.outputs.extend(sent.iter());
+                let targets = node.targets.clone();
+                for o in sent {
+                    for &target in &targets {
+                        self.nodes[target].machine.push_input(o);
+                    }
+                }
+                if let Some(e) = failed {
+                    return Err(e);
+                }
+            }
+
+            if self.nodes.iter().all(|node| node.machine.is_halted()) {
+                return Ok(Outcome::Halted);
+            }
+            if !progress {
+                return Ok(Outcome::Quiescent);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble, Limits};
+
+    // add 1 to every input, forever
+    fn increment() -> Vec<i64> {
+        assemble("loop: IN -> [x]\nADD [x], #1 -> [x]\nOUT [x]\nJNZ #1, loop\nx: .data 0").unwrap()
+    }
+
+    // add the first two inputs, then halt
+    fn sum() -> Vec<i64> {
+        assemble("IN -> [x]\nIN -> [y]\nADD [x], [y] -> [x]\nOUT [x]\nHLT\nx: .data 0\ny: .data 0")
+            .unwrap()
+    }
+
+    #[test]
+    fn chain() {
+        let mut network = Network::new();
+        let nodes: Vec<usize> = (0..3).map(|_| network.add_node(&increment())).collect();
+        network.connect(nodes[0], nodes[1]).unwrap();
+        network.connect(nodes[1], nodes[2]).unwrap();
+        network.push_input(nodes[0], 10).unwrap();
+        network.push_input(nodes[0], 20).unwrap();
+
+        assert_eq!(network.run().unwrap(), Outcome::Quiescent);
+        assert_eq!(network.outputs(nodes[0]).unwrap(), &vec![11, 21]);
+        assert_eq!(network.outputs(nodes[2]).unwrap(), &vec![13, 23]);
+    }
+
+    #[test]
+    fn fan_out_and_fan_in() {
+        let mut network = Network::new();
+        let source = network.add_node(&increment());
+        let left = network.add_node(&increment());
+        let right = network.add_node(&increment());
+        let sink = network.add_node(&sum());
+        network.connect(source, left).unwrap();
+        network.connect(source, right).unwrap();
+        network.connect(left, sink).unwrap();
+        network.connect(right, sink).unwrap();
+        network.push_input(source, 0).unwrap();
+
+        assert_eq!(network.run().unwrap(), Outcome::Quiescent);
+        assert_eq!(network.outputs(sink).unwrap(), &vec![4]);
+        assert!(network.machine(sink).unwrap().is_halted());
+    }
+
+    #[test]
+    fn ring_halts() {
+        // count down around a ring of two, passing on the final 0 before halting
+        let countdown = assemble(
+            "
+            loop:   IN -> [n]
+                    JZ [n], end
+                    ADD [n], #-1 -> [n]
+                    OUT [n]
+                    JNZ #1, loop
+            end:    OUT [n]
+                    HLT
+            n:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut network = Network::new();
+        let a = network.add_node(&countdown);
+        let b = network.add_node(&countdown);
+        network.connect(a, b).unwrap();
+        network.connect(b, a).unwrap();
+        network.push_input(a, 5).unwrap();
+
+        assert_eq!(network.run().unwrap(), Outcome::Halted);
+        assert_eq!(network.outputs(a).unwrap(), &vec![4, 2, 0, 0]);
+        assert_eq!(network.outputs(b).unwrap(), &vec![3, 1, 0]);
+    }
+
+    #[test]
+    fn checks_nodes() {
+        let mut network = Network::new();
+        let a = network.add_node(&increment());
+        let missing = Err(NetworkError::NoNode { node: 1 });
+        assert_eq!(network.connect(a, 1), missing);
+        assert_eq!(network.connect(1, a), missing);
+        assert_eq!(network.push_input(1, 0), missing);
+        assert_eq!(network.outputs(1), Err(NetworkError::NoNode { node: 1 }));
+        assert_eq!(
+            network.machine(1).unwrap_err().to_string(),
+            "there is no node 1"
+        );
+        assert!(network.connect(a, a).is_ok());
+    }
+
+    #[test]
+    fn keeps_outputs_when_out_of_fuel() {
+        // enough fuel for two trips round the loop, but not a third
+        let mut machine = Machine::new(increment());
+        machine.set_limits(Limits {
+            fuel: Some(9),
+            ..Limits::default()
+        });
+        let mut network = Network::new();
+        let b = network.add_node(&sum());
+        let a = network.add_machine(machine);
+        network.connect(a, b).unwrap();
+        for i in 1..=3 {
+            network.push_input(a, i).unwrap();
+        }
+
+        assert_eq!(
+            network.run(),
+            Err(VmError::OutOfFuel {
+                address: 2,
+                steps: 9
+            })
+        );
+        assert_eq!(network.outputs(a).unwrap(), &vec![2, 3]);
+        // and delivered, so the next machine to run can use them
+        assert!(network.run().is_err());
+        assert_eq!(network.outputs(b).unwrap(), &vec![5]);
+    }
+}
@@ -0,0 +1,395 @@
+mod addr;
+use crate::{Instruction, InstructionSet, Memory, Overflow, Promoted, VmError};
+pub use addr::*;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub enum OpCode {
+    Add {
+        opAddr1: Addr,
+        opAddr2: Addr,
+        destAddr: Addr,
+    },
+    Mult {
+        opAddr1: Addr,
+        opAddr2: Addr,
+        destAddr: Addr,
+    },
+    Read {
+        destAddr: Addr,
+    },
+    Write {
+        opAddr1: Addr,
+    },
+    JumpIf {
+        boolAddr: Addr,
+        jumpAddr: Addr,
+    },
+    JumpIfNot {
+        boolAddr: Addr,
+        jumpAddr: Addr,
+    },
+    SetIfLt {
+        opAddr1: Addr,
+        opAddr2: Addr,
+        destAddr: Addr,
+    }, // set to 1 or 0
+    SetIfEq {
+        opAddr1: Addr,
+        opAddr2: Addr,
+        destAddr: Addr,
+    },
+    SetRelBase {
+        relAddrChange: Addr,
+    },
+    Halt,
+    // anything registered in an `InstructionSet` beyond the above
+    Custom {
+        instruction: Arc<Instruction>,
+        operands: Vec<Addr>,
+    },
+}
+use OpCode::*;
+
+pub struct CurrentState<'data> {
+    pub data: &'data dyn Memory,
+    pub program_counter: i64,
+    pub relative_base: i64,
+}
+// decode with the standard instruction set
+impl TryFrom<CurrentState<'_>> for OpCode {
+    type Error = VmError;
+    fn try_from(cs: CurrentState) -> Result<OpCode, VmError> {
+        InstructionSet::shared_standard().decode(cs)
+    }
+}
+impl OpCode {
+    pub fn numFields(&self) -> i32 {
+        match self {
+            Add {
+                opAddr1: _,
+                opAddr2: _,
+                destAddr: _,
+            } => 3,
+            Mult {
+                opAddr1: _,
+                opAddr2: _,
+                destAddr: _,
+            } => 3,
+            Read { destAddr: _ } => 1,
+            Write { opAddr1: _ } => 1,
+            JumpIf {
+                boolAddr: _,
+                jumpAddr: _,
+            } => 2,
+            JumpIfNot {
+                boolAddr: _,
+                jumpAddr: _,
+            } => 2,
+            SetIfLt {
+                opAddr1: _,
+                opAddr2: _,
+                destAddr: _,
+            } => 3,
+            SetIfEq {
+                opAddr1: _,
+                opAddr2: _,
+                destAddr: _,
+            } => 3,
+            SetRelBase { relAddrChange: _ } => 1,
+            Halt => 0,
+            Custom { operands, .. } => operands.len() as i32,
+        }
+    }
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Add { .. } => "ADD",
+            Mult { .. } => "MUL",
+            Read { .. } => "IN",
+            Write { .. } => "OUT",
+            JumpIf { .. } => "JNZ",
+            JumpIfNot { .. } => "JZ",
+            SetIfLt { .. } => "LT",
+            SetIfEq { .. } => "EQ",
+            SetRelBase { .. } => "ARB",
+            Halt => "HLT",
+            Custom { instruction, .. } => instruction.mnemonic,
+        }
+    }
+    // the operands an instruction reads, in order
+    pub fn sources(&self) -> Vec<&Addr> {
+        match self {
+            Add {
+                opAddr1, opAddr2, ..
+            }
+            | Mult {
+                opAddr1, opAddr2, ..
+            }
+            | SetIfLt {
+                opAddr1, opAddr2, ..
+            }
+            | SetIfEq {
+                opAddr1, opAddr2, ..
+            } => vec![opAddr1, opAddr2],
+            Read { .. } | Halt => vec![],
+            Write { opAddr1 } => vec![opAddr1],
+            JumpIf { boolAddr, jumpAddr } | JumpIfNot { boolAddr, jumpAddr } => {
+                vec![boolAddr, jumpAddr]
+            }
+            SetRelBase { relAddrChange } => vec![relAddrChange],
+            Custom {
+                instruction,
+                operands,
+            } => operands[..instruction.sources].iter().collect(),
+        }
+    }
+    // the operand an instruction writes to, if any
+    pub fn dest(&self) -> Option<&Addr> {
+        match self {
+            Add { destAddr, .. }
+            | Mult { destAddr, .. }
+            | Read { destAddr }
+            | SetIfLt { destAddr, .. }
+            | SetIfEq { destAddr, .. } => Some(destAddr),
+            Custom {
+                instruction,
+                operands,
+            } if instruction.writes => operands.last(),
+            _ => None,
+        }
+    }
+    // Point relative operands at `relative_base`, to reuse an instruction decoded under another.
+    pub fn rebase(&mut self, relative_base: i64) {
+        let rebase = |addr: &mut Addr| {
+            if let Addr::Relative(Rel(_, rb)) = addr {
+                *rb = relative_base;
+            }
+        };
+        match self {
+            Add {
+                opAddr1,
+                opAddr2,
+                destAddr,
+            }
+            | Mult {
+                opAddr1,
+                opAddr2,
+                destAddr,
+            }
+            | SetIfLt {
+                opAddr1,
+                opAddr2,
+                destAddr,
+            }
+            | SetIfEq {
+                opAddr1,
+                opAddr2,
+                destAddr,
+            } => {
+                rebase(opAddr1);
+                rebase(opAddr2);
+                rebase(destAddr);
+            }
+            JumpIf { boolAddr, jumpAddr } | JumpIfNot { boolAddr, jumpAddr } => {
+                rebase(boolAddr);
+                rebase(jumpAddr);
+            }
+            Read { destAddr: addr }
+            | Write { opAddr1: addr }
+            | SetRelBase {
+                relAddrChange: addr,
+            } => rebase(addr),
+            Halt => (),
+            Custom { operands, .. } => operands.iter_mut().for_each(rebase),
+        }
+    }
+    pub fn executeIntruction(
+        &self,
+        rel_base: &mut i64,
+        data: &mut dyn Memory,
+        i: &mut i64,
+        input: &mut dyn Iterator<Item = i64>,
+        overflow: Overflow,
+        promoted: &mut Promoted,
+    ) -> Result<Option<i64>, VmError> {
+        let pc = *i;
+        let overflowed = VmError::Overflow { address: pc };
+        let mut jumped = false;
+        let retval = match self {
+            Add {
+                opAddr1,
+                opAddr2,
+                destAddr,
+            } => {
+                let (a, b) = (
+                    value(opAddr1, data, promoted, pc)?,
+                    value(opAddr2, data, promoted, pc)?,
+                );
+                let val = overflow.add(a, b).ok_or(overflowed)?;
+                store(destAddr, val, data, promoted, pc)?;
+                None
+            }
+            Mult {
+                opAddr1,
+                opAddr2,
+                destAddr,
+            } => {
+                let (a, b) = (
+                    value(opAddr1, data, promoted, pc)?,
+                    value(opAddr2, data, promoted, pc)?,
+                );
+                let val = overflow.mul(a, b).ok_or(overflowed)?;
+                store(destAddr, val, data, promoted, pc)?;
+                None
+            }
+            Read { destAddr } => {
+                let val = input
+                    .next()
+                    .ok_or(VmError::InputExhausted { address: pc })?;
+                store(destAddr, val.into(), data, promoted, pc)?;
+                None
+            }
+            Write { opAddr1 } => Some(word(opAddr1, data, promoted, pc)?),
+            JumpIf { boolAddr, jumpAddr } => {
+                if value(boolAddr, data, promoted, pc)? != 0 {
+                    *i = jumpTarget(jumpAddr, data, promoted, pc)?;
+                    jumped = true;
+                }
+                None
+            }
+            JumpIfNot { boolAddr, jumpAddr } => {
+                if value(boolAddr, data, promoted, pc)? == 0 {
+                    *i = jumpTarget(jumpAddr, data, promoted, pc)?;
+                    jumped = true;
+                }
+                None
+            }
+            SetIfLt {
+                opAddr1,
+                opAddr2,
+                destAddr,
+            } => {
+                let val = value(opAddr1, data, promoted, pc)? < value(opAddr2, data, promoted, pc)?;
+                store(destAddr, val.into(), data, promoted, pc)?;
+                None
+            }
+            SetIfEq {
+                opAddr1,
+                opAddr2,
+                destAddr,
+            } => {
+                let val =
+                    value(opAddr1, data, promoted, pc)? == value(opAddr2, data, promoted, pc)?;
+                store(destAddr, val.into(), data, promoted, pc)?;
+                None
+            }
+            SetRelBase { relAddrChange } => {
+                let change = word(relAddrChange, data, promoted, pc)?;
+                *rel_base = rel_base
+                    .checked_add(change)
+                    .ok_or(VmError::AddressOverflow { address: pc + 1 })?;
+                None
+            }
+            Halt => {
+                *i = -1;
+                jumped = true;
+                None
+            }
+            Custom { instruction, .. } => {
+                let mut values = Vec::with_capacity(instruction.sources);
+                for addr in self.sources() {
+                    values.push(word(addr, data, promoted, pc)?);
+                }
+                let effect = instruction
+                    .execute(&values)
+                    .ok_or(VmError::InstructionFault {
+                        address: pc,
+                        opcode: instruction.code,
+                    })?;
+                if let (Some(val), Some(dest)) = (effect.write, self.dest()) {
+                    store(dest, val.into(), data, promoted, pc)?;
+                }
+                if let Some(target) = effect.jump {
+                    if target < 0 {
+                        return Err(VmError::NegativeAddress { address: target });
+                    }
+                    *i = target;
+                    jumped = true;
+                }
+                effect.output
+            }
+        };
+        if !jumped {
+            *i += self.numFields() as i64 + 1;
+        }
+        Ok(retval)
+    }
+}
+
+// An operand's value, which is only wider than a word if it's `promoted`. A promoted word can't
+// be used as an address.
+fn value(addr: &Addr, data: &dyn Memory, promoted: &Promoted, pc: i64) -> Result<i128, VmError> {
+    if promoted.is_empty() {
+        return Ok(addr.getValue(data)?.into());
+    }
+    if let Addr::Position(Pos(field)) | Addr::Relative(Rel(field, _)) = addr {
+        if promoted.contains_key(&(*field as usize)) {
+            return Err(VmError::Overflow { address: pc });
+        }
+    }
+    let address = addr.resolve(data)?;
+    Ok(match promoted.get(&address) {
+        Some(wide) => *wide,
+        None => data.read(address).into(),
+    })
+}
+
+// an operand's value where only a word will do
+fn word(addr: &Addr, data: &dyn Memory, promoted: &Promoted, pc: i64) -> Result<i64, VmError> {
+    let value = value(addr, data, promoted, pc)?;
+    i64::try_from(value).map_err(|_| VmError::Overflow { address: pc })
+}
+
+// Write `val`, keeping it in `promoted` if it doesn't fit in a word and memory gets it clamped.
+fn store(
+    dest: &Addr,
+    val: i128,
+    data: &mut dyn Memory,
+    promoted: &mut Promoted,
+    pc: i64,
+) -> Result<(), VmError> {
+    if let Addr::Position(Pos(field)) | Addr::Relative(Rel(field, _)) = dest {
+        if promoted.contains_key(&(*field as usize)) {
+            return Err(VmError::Overflow { address: pc });
+        }
+    }
+    let narrow = i64::try_from(val);
+    *dest.getDest(data)? = match narrow {
+        Ok(val) => val,
+        Err(_) if val < 0 => i64::MIN,
+        Err(_) => i64::MAX,
+    };
+    if narrow.is_err() || !promoted.is_empty() {
+        let address = dest.resolve(data)?;
+        match narrow {
+            Ok(_) => promoted.remove(&address),
+            Err(_) => promoted.insert(address, val),
+        };
+    }
+    Ok(())
+}
+
+// a negative program counter means halted, so don't let a jump get there by accident
+fn jumpTarget(
+    jumpAddr: &Addr,
+    data: &dyn Memory,
+    promoted: &Promoted,
+    pc: i64,
+) -> Result<i64, VmError> {
+    let target = word(jumpAddr, data, promoted, pc)?;
+    if target < 0 {
+        return Err(VmError::NegativeAddress { address: target });
+    }
+    Ok(target)
+}
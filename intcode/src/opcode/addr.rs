@@ -0,0 +1,128 @@
+use crate::{Memory, VmError};
+use std::convert::TryFrom;
+
+#[allow(clippy::enum_variant_names)]
+pub enum Mode {
+    ImmediateMode,
+    PositionMode,
+    RelativeMode,
+}
+use Mode::*;
+impl TryFrom<i64> for Mode {
+    type Error = String;
+    fn try_from(i: i64) -> Result<Self, Self::Error> {
+        match i {
+            0 => Ok(PositionMode),
+            1 => Ok(ImmediateMode),
+            2 => Ok(RelativeMode),
+            _ => Err(format!("{:?} is not a valid Mode, must be in [0,1,2]", i)),
+        }
+    }
+}
+impl From<&Mode> for i64 {
+    fn from(mode: &Mode) -> i64 {
+        match mode {
+            PositionMode => 0,
+            ImmediateMode => 1,
+            RelativeMode => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Addr {
+    Immediate(Imm),
+    Position(Pos),
+    Relative(Rel),
+}
+impl From<(i64, Mode, i64)> for Addr {
+    fn from(tuple: (i64, Mode, i64)) -> Addr {
+        let (i, mode, relative_base) = (tuple.0, tuple.1, tuple.2);
+
+        match mode {
+            ImmediateMode => Immediate(Imm(i)),
+            PositionMode => Position(Pos(i)),
+            RelativeMode => Relative(Rel(i, relative_base)),
+        }
+    }
+}
+impl DataAddr for Addr {
+    fn getData<'a>(&self, data: &'a mut dyn Memory) -> Result<&'a mut i64, VmError> {
+        match self {
+            Immediate(imm) => imm.getData(data),
+            Position(pos) => pos.getData(data),
+            Relative(rel) => rel.getData(data),
+        }
+    }
+}
+impl Addr {
+    // The address of the cell this operand refers to, without touching memory.
+    pub fn resolve(&self, data: &dyn Memory) -> Result<usize, VmError> {
+        match self {
+            Immediate(Imm(i)) => checked(*i),
+            Position(Pos(i)) => checked(data.read(checked(*i)?)),
+            Relative(Rel(i, relative_base)) => {
+                checked(relative(*i, data.read(checked(*i)?), *relative_base)?)
+            }
+        }
+    }
+    // The value this operand gives. Reading never touches memory, so what was never written
+    // stays unallocated and doesn't count towards `size`.
+    pub fn getValue(&self, data: &dyn Memory) -> Result<i64, VmError> {
+        Ok(data.read(self.resolve(data)?))
+    }
+    // The cell an operand the instruction writes to refers to.
+    pub fn getDest<'a>(&self, data: &'a mut dyn Memory) -> Result<&'a mut i64, VmError> {
+        match self {
+            Immediate(Imm(i)) => Err(VmError::WriteToImmediate { address: *i }),
+            _ => self.getData(data),
+        }
+    }
+}
+use Addr::*;
+
+fn checked(address: i64) -> Result<usize, VmError> {
+    if address < 0 {
+        return Err(VmError::NegativeAddress { address });
+    }
+    Ok(address as usize)
+}
+
+// `field` is where the operand is, holding `offset` from `relative_base`
+fn relative(field: i64, offset: i64, relative_base: i64) -> Result<i64, VmError> {
+    offset
+        .checked_add(relative_base)
+        .ok_or(VmError::AddressOverflow { address: field })
+}
+
+pub trait DataAddr {
+    fn getData<'a>(&self, data: &'a mut dyn Memory) -> Result<&'a mut i64, VmError>;
+}
+#[derive(Debug, Clone)]
+pub struct Imm(pub i64);
+impl DataAddr for Imm {
+    fn getData<'a>(&self, data: &'a mut dyn Memory) -> Result<&'a mut i64, VmError> {
+        Ok(data.cell(checked(self.0)?))
+    }
+}
+#[derive(Debug, Clone)]
+pub struct Pos(pub i64);
+impl DataAddr for Pos {
+    fn getData<'a>(&self, data: &'a mut dyn Memory) -> Result<&'a mut i64, VmError> {
+        let val = data.read(checked(self.0)?);
+        Ok(data.cell(checked(val)?))
+    }
+}
+impl From<Pos> for Addr {
+    fn from(pos: Pos) -> Addr {
+        Position(pos)
+    }
+}
+#[derive(Debug, Clone)]
+pub struct Rel(pub i64, pub i64);
+impl DataAddr for Rel {
+    fn getData<'a>(&self, data: &'a mut dyn Memory) -> Result<&'a mut i64, VmError> {
+        let val = data.read(checked(self.0)?);
+        Ok(data.cell(checked(relative(self.0, val, self.1)?)?))
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+// What ADD and MUL do when the result doesn't fit in a word, the same in debug and release
+// builds. Address arithmetic, moving the relative base or resolving a relative operand, always
+// stops with `VmError::AddressOverflow` whatever the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    // stop with `VmError::Overflow`
+    #[default]
+    Trap,
+    // two's complement wraparound
+    Wrap,
+    // clamp to `i64::MIN` or `i64::MAX`
+    Saturate,
+    // Keep the result as a 128-bit word in the machine's `Promoted` cells. ADD, MUL, LT and EQ
+    // work on those at full width, but anything that needs a word, an output, a jump target, an
+    // address, the relative base or a custom instruction's operand, stops with
+    // `VmError::Overflow` if it gets one that doesn't fit, as does overflowing 128 bits.
+    Promote,
+}
+impl Overflow {
+    // `None` means trap
+    pub fn add(self, a: i128, b: i128) -> Option<i128> {
+        self.fit(a.checked_add(b)?)
+    }
+
+    pub fn mul(self, a: i128, b: i128) -> Option<i128> {
+        self.fit(a.checked_mul(b)?)
+    }
+
+    // the exact result of adding or multiplying words always fits in 128 bits
+    fn fit(self, value: i128) -> Option<i128> {
+        let (min, max) = (i128::from(i64::MIN), i128::from(i64::MAX));
+        match self {
+            Overflow::Trap => i64::try_from(value).ok().map(i128::from),
+            Overflow::Wrap => Some(i128::from(value as i64)),
+            Overflow::Saturate => Some(value.clamp(min, max)),
+            Overflow::Promote => Some(value),
+        }
+    }
+}
+impl FromStr for Overflow {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "trap" => Ok(Overflow::Trap),
+            "wrap" => Ok(Overflow::Wrap),
+            "saturate" => Ok(Overflow::Saturate),
+            "promote" => Ok(Overflow::Promote),
+            _ => Err(format!(
+                "{:?} is not an overflow policy, must be trap, wrap, saturate or promote",
+                s
+            )),
+        }
+    }
+}
+
+// The cells holding results too wide for a word under `Overflow::Promote`, by address. Memory
+// holds them clamped to a word, which is what anything looking at memory rather than running
+// the program sees.
+pub type Promoted = BTreeMap<usize, i128>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, Status, VmError};
+
+    // output [a] * [b], then [a] + [b]
+    fn program(a: i64, b: i64) -> Vec<i64> {
+        vec![2, 13, 14, 15, 4, 15, 1, 13, 14, 15, 4, 15, 99, a, b, 0]
+    }
+
+    fn run(a: i64, b: i64, overflow: Overflow) -> Result<Vec<i64>, VmError> {
+        let mut machine = Machine::new(program(a, b));
+        machine.set_overflow(overflow);
+        let mut output = Vec::new();
+        while let Status::Output(o) = machine.run_until()? {
+            output.push(o);
+        }
+        Ok(output)
+    }
+
+    #[test]
+    fn policies() {
+        let big = 1 << 62;
+        assert_eq!(
+            run(big, 2, Overflow::Trap),
+            Err(VmError::Overflow { address: 0 })
+        );
+        assert_eq!(run(big, 2, Overflow::Wrap), Ok(vec![i64::MIN, big + 2]));
+        assert_eq!(run(big, 2, Overflow::Saturate), Ok(vec![i64::MAX, big + 2]));
+        assert_eq!(
+            run(i64::MAX, 1, Overflow::Trap),
+            Err(VmError::Overflow { address: 6 })
+        );
+        assert_eq!(
+            run(-big, 3, Overflow::Saturate),
+            Ok(vec![i64::MIN, 3 - big])
+        );
+        // address arithmetic traps whatever the policy
+        let far = vec![109, i64::MAX, 109, 1, 99];
+        for &overflow in &[
+            Overflow::Trap,
+            Overflow::Wrap,
+            Overflow::Saturate,
+            Overflow::Promote,
+        ] {
+            let mut machine = Machine::new(far.clone());
+            machine.set_overflow(overflow);
+            assert_eq!(
+                machine.run_until(),
+                Err(VmError::AddressOverflow { address: 3 })
+            );
+        }
+        // day09's test_2 is nowhere near the edge
+        assert_eq!(
+            run(34915192, 34915192, Overflow::Trap),
+            Ok(vec![1219070632396864, 69830384])
+        );
+    }
+
+    #[test]
+    fn promote() {
+        let big = 1 << 62;
+        // the product is kept, but can't be output
+        assert_eq!(
+            run(big, 2, Overflow::Promote),
+            Err(VmError::Overflow { address: 4 })
+        );
+        assert_eq!(
+            run(34915192, 34915192, Overflow::Promote),
+            Ok(vec![1219070632396864, 69830384])
+        );
+
+        // [40] = [40] * 4 and [41] = [41] * -4 are both too wide for a word, then output
+        // [40] < [41], [40] == i64::MAX and [40] + [41] + 7
+        let mut data = vec![
+            1002,
+            40,
+            4,
+            40,
+            1002,
+            41,
+            -4,
+            41,
+            7,
+            40,
+            41,
+            42,
+            4,
+            42,
+            1008,
+            40,
+            i64::MAX,
+            42,
+            4,
+            42,
+            1,
+            40,
+            41,
+            42,
+            1001,
+            42,
+            7,
+            42,
+            4,
+            42,
+            99,
+        ];
+        data.resize(40, 0);
+        data.extend(&[big, big, 0]);
+        let mut machine = Machine::new(data);
+        machine.set_overflow(Overflow::Promote);
+        let mut output = Vec::new();
+        while let Status::Output(o) = machine.run_until().unwrap() {
+            output.push(o);
+        }
+        assert_eq!(output, vec![0, 0, 7]);
+        // memory has them clamped
+        assert_eq!(machine.data()[40], i64::MAX);
+        assert_eq!(machine.data()[41], i64::MIN);
+        assert_eq!(machine.promoted().get(&40), Some(&(1 << 64)));
+        assert_eq!(machine.promoted().get(&41), Some(&-(1 << 64)));
+        assert_eq!(machine.promoted().len(), 2);
+
+        // a promoted word can't be used as an address, nor run
+        let mut machine = Machine::new(vec![1002, 5, 4, 5, 4, big, 99]);
+        machine.set_overflow(Overflow::Promote);
+        assert_eq!(machine.run_until(), Err(VmError::Overflow { address: 4 }));
+        let mut machine = Machine::new(vec![1002, 4, 4, 4, big]);
+        machine.set_overflow(Overflow::Promote);
+        assert_eq!(machine.run_until(), Err(VmError::Overflow { address: 4 }));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("wrap".parse(), Ok(Overflow::Wrap));
+        assert_eq!("promote".parse(), Ok(Overflow::Promote));
+        assert!("bogus".parse::<Overflow>().is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{render_instruction, Addr, Memory, OpCode};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone)]
+struct Hotspot {
+    count: usize,
+    // decoded the first time the address ran
+    instruction: String,
+}
+
+// Counts of what a run spent its time on, from the moment profiling was turned on.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    steps: usize,
+    executions: HashMap<usize, Hotspot>,
+    opcodes: HashMap<&'static str, usize>,
+    // operand reads and writes, not counting instruction fetches or immediate operands
+    reads: HashMap<usize, usize>,
+    writes: HashMap<usize, usize>,
+}
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Count `opcode`, about to execute at `program_counter`.
+    pub(crate) fn record(&mut self, opcode: &OpCode, data: &dyn Memory, program_counter: i64) {
+        self.steps += 1;
+        *self.opcodes.entry(opcode.mnemonic()).or_insert(0) += 1;
+        self.executions
+            .entry(program_counter as usize)
+            .or_insert_with(|| Hotspot {
+                count: 0,
+                instruction: render_instruction(opcode, data),
+            })
+            .count += 1;
+
+        let data_address = |addr: &Addr| match addr {
+            Addr::Immediate(_) => None,
+            _ => addr.resolve(data).ok(),
+        };
+        for address in opcode.sources().into_iter().filter_map(data_address) {
+            *self.reads.entry(address).or_insert(0) += 1;
+        }
+        if let Some(address) = opcode.dest().and_then(data_address) {
+            *self.writes.entry(address).or_insert(0) += 1;
+        }
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn executions(&self, address: usize) -> usize {
+        self.executions.get(&address).map_or(0, |hot| hot.count)
+    }
+
+    pub fn opcode_count(&self, mnemonic: &str) -> usize {
+        *self.opcodes.get(mnemonic).unwrap_or(&0)
+    }
+
+    pub fn reads(&self, address: usize) -> usize {
+        *self.reads.get(&address).unwrap_or(&0)
+    }
+
+    pub fn writes(&self, address: usize) -> usize {
+        *self.writes.get(&address).unwrap_or(&0)
+    }
+
+    // (address, executions) for every address that ran, most executed first
+    pub fn hottest(&self) -> Vec<(usize, usize)> {
+        hottest(self.executions.iter().map(|(&a, hot)| (a, hot.count)))
+    }
+
+    // The `top` most executed instructions, opcodes and memory cells.
+    pub fn report(&self, top: usize) -> String {
+        let percent = |count: usize| 100.0 * count as f64 / self.steps.max(1) as f64;
+        let mut s = String::new();
+        writeln!(s, "{} steps", self.steps).unwrap();
+
+        writeln!(s, "\nhottest instructions:").unwrap();
+        for (address, count) in self.hottest().into_iter().take(top) {
+            let instruction = &self.executions[&address].instruction;
+            writeln!(
+                s,
+                "{:>12} {:>5.1}%  {:>6}: {}",
+                count,
+                percent(count),
+                address,
+                instruction
+            )
+            .unwrap();
+        }
+
+        writeln!(s, "\nopcodes:").unwrap();
+        let mut opcodes: Vec<(&str, usize)> = self.opcodes.iter().map(|(&m, &c)| (m, c)).collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        for (mnemonic, count) in opcodes {
+            writeln!(s, "{:>12} {:>5.1}%  {}", count, percent(count), mnemonic).unwrap();
+        }
+
+        for (name, counts) in &[("reads", &self.reads), ("writes", &self.writes)] {
+            writeln!(s, "\nmost {}:", name).unwrap();
+            for (address, count) in hottest(counts.iter().map(|(&a, &c)| (a, c)))
+                .into_iter()
+                .take(top)
+            {
+                writeln!(s, "{:>12}         [{}]", count, address).unwrap();
+            }
+        }
+        s
+    }
+}
+
+// highest count first, then lowest address
+fn hottest(counts: impl Iterator<Item = (usize, usize)>) -> Vec<(usize, usize)> {
+    let mut counts: Vec<(usize, usize)> = counts.collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{assemble, Machine, Status};
+
+    #[test]
+    fn counts_a_loop() {
+        // count down from the input, adding each number to a total
+        let program = assemble(
+            "
+                    IN -> [n]
+            loop:   ADD [total], [n] -> [total]
+                    ADD [n], #-1 -> [n]
+                    JNZ [n], loop
+                    OUT [total]
+                    HLT
+            n:      .data 0
+            total:  .data 0
+            ",
+        )
+        .unwrap();
+        let (n, total) = (program.len() - 2, program.len() - 1);
+        let mut machine = Machine::new(program);
+        machine.start_profile();
+        machine.push_input(10);
+        assert_eq!(machine.run_until().unwrap(), Status::Output(55));
+        assert_eq!(machine.run_until().unwrap(), Status::Halted);
+
+        let profile = machine.take_profile().unwrap();
+        assert_eq!(profile.steps(), machine.steps());
+        assert_eq!(profile.steps(), 1 + 3 * 10 + 2);
+        assert_eq!(profile.executions(2), 10);
+        assert_eq!(profile.opcode_count("ADD"), 20);
+        assert_eq!(profile.opcode_count("JNZ"), 10);
+        assert_eq!(profile.writes(n), 11);
+        assert_eq!(profile.reads(n), 30);
+        assert_eq!(profile.reads(total), 11);
+        assert_eq!(profile.writes(total), 10);
+        assert_eq!(&profile.hottest()[..3], &[(2, 10), (6, 10), (10, 10)]);
+
+        let report = profile.report(3);
+        assert!(report.starts_with("33 steps\n"));
+        let line = format!(
+            "          10  30.3%       2: ADD [{}], [{}] -> [{}]\n",
+            total, n, total
+        );
+        assert!(report.contains(&line));
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{Limits, Overflow};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum ProgramError {
+    Io(io::Error),
+    // `offset` is in bytes from the start of the text
+    Malformed { offset: usize, token: String },
+}
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProgramError::Io(e) => write!(f, "{}", e),
+            ProgramError::Malformed { offset, token } if token.is_empty() => {
+                write!(f, "missing value at byte {}", offset)
+            }
+            ProgramError::Malformed { offset, token } => {
+                write!(f, "malformed value {:?} at byte {}", token, offset)
+            }
+        }
+    }
+}
+impl Error for ProgramError {}
+impl From<io::Error> for ProgramError {
+    fn from(e: io::Error) -> Self {
+        ProgramError::Io(e)
+    }
+}
+
+// An intcode program as read from a puzzle input: comma separated values, with any whitespace
+// (newlines included) around them and `;` comments running to the end of the line. A trailing
+// comma is fine, an empty value anywhere else is not.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Program {
+    data: Vec<i64>,
+}
+impl Program {
+    pub fn parse(text: &str) -> Result<Self, ProgramError> {
+        // (where the value starts, and the span of its text if it has any) for every value
+        let mut fields: Vec<(usize, Option<(usize, usize)>)> = Vec::new();
+        let mut field = (0, None);
+        let mut in_comment = false;
+        for (i, c) in text.char_indices() {
+            if in_comment {
+                in_comment = c != '\n';
+            } else if c == ';' {
+                in_comment = true;
+            } else if c == ',' {
+                fields.push(field);
+                field = (i + 1, None);
+            } else if !c.is_whitespace() {
+                let start = field.1.map_or(i, |(start, _)| start);
+                field.1 = Some((start, i + c.len_utf8()));
+            }
+        }
+        // a missing last value is just a trailing comma
+        if field.1.is_some() {
+            fields.push(field);
+        }
+
+        let data = fields
+            .into_iter()
+            .map(|(offset, span)| match span {
+                Some((start, end)) => {
+                    text[start..end]
+                        .parse()
+                        .map_err(|_| ProgramError::Malformed {
+                            offset: start,
+                            token: text[start..end].to_string(),
+                        })
+                }
+                None => Err(ProgramError::Malformed {
+                    offset,
+                    token: String::new(),
+                }),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Program { data })
+    }
+
+    pub fn from_reader(reader: &mut dyn Read) -> Result<Self, ProgramError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        Self::parse(&text)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ProgramError> {
+        Self::from_reader(&mut File::open(path)?)
+    }
+
+    pub fn data(&self) -> &[i64] {
+        &self.data
+    }
+}
+impl FromStr for Program {
+    type Err = ProgramError;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+    }
+}
+impl From<Program> for Vec<i64> {
+    fn from(program: Program) -> Self {
+        program.data
+    }
+}
+
+// Command line options take a value, `--name value`, apart from these.
+const FLAGS: [&str; 1] = ["--ascii"];
+
+pub fn flag(name: &str) -> bool {
+    env::args().skip(1).any(|arg| arg == name)
+}
+
+fn option(name: &str) -> Option<String> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == name {
+            return args.next();
+        }
+    }
+    None
+}
+
+// The file given with `--input <file>` on the command line, if any.
+pub fn input_arg() -> Option<String> {
+    option("--input")
+}
+
+// `--fuel <instructions>` and `--time-limit <seconds>` from the command line.
+pub fn limits_arg() -> Result<Limits, String> {
+    Ok(Limits {
+        fuel: option("--fuel").map(|value| fuel(&value)).transpose()?,
+        time: option("--time-limit")
+            .map(|value| time_limit(&value))
+            .transpose()?,
+    })
+}
+
+fn fuel(value: &str) -> Result<usize, String> {
+    value.parse().map_err(|_| {
+        format!(
+            "--fuel takes a whole number of instructions, not {:?}",
+            value
+        )
+    })
+}
+
+fn time_limit(value: &str) -> Result<Duration, String> {
+    let seconds = value.parse().ok();
+    seconds
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("--time-limit takes a number of seconds, not {:?}", value))
+}
+
+// `--overflow trap|wrap|saturate|promote` from the command line.
+pub fn overflow_arg() -> Result<Overflow, String> {
+    option("--overflow").map_or(Ok(Overflow::default()), |policy| policy.parse())
+}
+
+// Whatever is on the command line besides options.
+pub fn positional_args() -> Vec<String> {
+    let mut positional = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if FLAGS.contains(&arg.as_str()) {
+            continue;
+        } else if arg.starts_with("--") {
+            args.next();
+        } else {
+            positional.push(arg);
+        }
+    }
+    positional
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn malformed(text: &str) -> (usize, String) {
+        match Program::parse(text) {
+            Err(ProgramError::Malformed { offset, token }) => (offset, token),
+            other => panic!("expected a malformed value, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn whitespace_comments_and_negatives() {
+        let text = "; add then halt\n1, 5,-1 ,\n  0,\t99 ; done\n,-7,\n";
+        assert_eq!(Program::parse(text).unwrap().data(), &[1, 5, -1, 0, 99, -7]);
+        assert_eq!(Program::parse("").unwrap().data(), &[] as &[i64]);
+        assert_eq!(
+            Program::parse("  ; nothing\n").unwrap().data(),
+            &[] as &[i64]
+        );
+    }
+
+    #[test]
+    fn reports_offsets() {
+        assert_eq!(malformed("1,2,x3,4"), (4, "x3".to_string()));
+        assert_eq!(malformed("1,\n2 3,4"), (3, "2 3".to_string()));
+        assert_eq!(malformed("1,,2"), (2, String::new()));
+        assert_eq!(malformed(",1"), (0, String::new()));
+        assert_eq!(
+            malformed("1,99999999999999999999"),
+            (2, "99999999999999999999".to_string())
+        );
+    }
+
+    #[test]
+    fn from_reader() {
+        let program = Program::from_reader(&mut "104,42,99\n".as_bytes()).unwrap();
+        assert_eq!(Vec::from(program), vec![104, 42, 99]);
+    }
+
+    #[test]
+    fn limits() {
+        assert_eq!(fuel("1000"), Ok(1000));
+        for bad in &["-1", "2.5", "lots", ""] {
+            assert_eq!(
+                fuel(bad),
+                Err(format!(
+                    "--fuel takes a whole number of instructions, not {:?}",
+                    bad
+                ))
+            );
+        }
+        assert_eq!(time_limit("1.5"), Ok(Duration::from_millis(1500)));
+        for bad in &["-1", "NaN", "soon"] {
+            assert!(time_limit(bad).is_err());
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use crate::{Machine, Status, VmError};
+use std::collections::VecDeque;
+
+// Packets sent here go to the NAT rather than to a machine.
+pub const NAT: i64 = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet {
+    // `NAT` for the packets the NAT sends
+    pub from: i64,
+    pub to: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+// Everything that happens on the network, in the order it happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    // to a machine, or to the NAT
+    Sent(Packet),
+    // to an address with nothing there
+    Dropped(Packet),
+    // Nothing was sent or received for a whole round, so the NAT sent machine 0 the last packet
+    // it was sent, if it has been sent any.
+    Idle { round: usize, wake: Option<Packet> },
+}
+
+// Watches the traffic on a network, and says when to stop it.
+pub trait Traffic {
+    // true to stop the network straight after `event`
+    fn observe(&mut self, event: &Event) -> bool;
+}
+impl<F: FnMut(&Event) -> bool> Traffic for F {
+    fn observe(&mut self, event: &Event) -> bool {
+        self(event)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Host {
+    machine: Machine,
+    queue: VecDeque<(i64, i64)>,
+    // what it has output of a packet it hasn't finished sending
+    partial: Vec<i64>,
+}
+
+// Machines that address packets to each other rather than being wired together: each boots with
+// its address as its first input, sends a packet by outputting the destination address then X
+// and Y, and reads X then Y of the next packet queued for it, or -1 when there is none.
+//
+// Machines take turns in address order, each getting at most one packet and then running until
+// it wants more input, so a run is deterministic. A round in which nobody sent or received
+// anything means the network is idle, and the NAT at address `NAT` gets it going again.
+#[derive(Debug, Clone)]
+pub struct Router {
+    hosts: Vec<Host>,
+    nat: Option<Packet>,
+    // rounds finished so far
+    rounds: usize,
+    // the next machine to have a turn this round, and whether anything has been sent or received
+    turn: usize,
+    busy: bool,
+    // whether nothing was sent or received in the last round
+    idle: bool,
+}
+impl Router {
+    // Boot `size` machines, each running its own copy of `program`. There can't be a machine
+    // at `NAT` or beyond, as packets sent there go to the NAT.
+    pub fn new(program: &[i64], size: usize) -> Result<Self, String> {
+        if size > NAT as usize {
+            return Err(format!(
+                "can't have {} machines, the NAT is at {}",
+                size, NAT
+            ));
+        }
+        let hosts = (0..size)
+            .map(|address| {
+                let mut machine = Machine::new(program.to_vec());
+                machine.push_input(address as i64);
+                Host {
+                    machine,
+                    queue: VecDeque::new(),
+                    partial: Vec::new(),
+                }
+            })
+            .collect();
+        Ok(Router {
+            hosts,
+            nat: None,
+            rounds: 0,
+            turn: 0,
+            busy: false,
+            idle: false,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.hosts.len()
+    }
+
+    pub fn machine(&self, address: usize) -> &Machine {
+        &self.hosts[address].machine
+    }
+
+    // (X, Y) of every packet waiting for `address`
+    pub fn queue(&self, address: usize) -> &VecDeque<(i64, i64)> {
+        &self.hosts[address].queue
+    }
+
+    // the last packet sent to the NAT
+    pub fn nat(&self) -> Option<Packet> {
+        self.nat
+    }
+
+    pub fn rounds(&self) -> usize {
+        self.rounds
+    }
+
+    // Run until `traffic` says to stop, returning the event it stopped on, or until nothing more
+    // can happen because every machine has halted or the network is idle with nothing at the NAT.
+    pub fn run(&mut self, traffic: &mut dyn Traffic) -> Result<Option<Event>, VmError> {
+        loop {
+            if let Some(event) = self.round(traffic)? {
+                return Ok(Some(event));
+            }
+            let halted = self.hosts.iter().all(|host| host.machine.is_halted());
+            if halted || (self.idle && self.nat.is_none()) {
+                return Ok(None);
+            }
+        }
+    }
+
+    // Give every machine still to have a turn this round one, returning the event `traffic`
+    // stopped on, if any. A stop comes at the end of the turn it happened in, so every packet
+    // sent is delivered and the next call carries on with the next machine.
+    pub fn round(&mut self, traffic: &mut dyn Traffic) -> Result<Option<Event>, VmError> {
+        while self.turn < self.hosts.len() {
+            let address = self.turn;
+            self.turn += 1;
+            let host = &mut self.hosts[address];
+            if host.machine.is_halted() {
+                continue;
+            }
+            match host.queue.pop_front() {
+                Some((x, y)) => {
+                    host.machine.push_input(x);
+                    host.machine.push_input(y);
+                    self.busy = true;
+                }
+                None => host.machine.push_input(-1),
+            }
+
+            let mut sent = Vec::new();
+            loop {
+                match host.machine.run_until()? {
+                    Status::Output(o) => host.partial.push(o),
+                    Status::OutOfFuel { address, steps } => {
+                        return Err(VmError::OutOfFuel { address, steps })
+                    }
+                    Status::NeedsInput | Status::Halted => break,
+                }
+                if let [to, x, y] = host.partial[..] {
+                    host.partial.clear();
+                    let from = address as i64;
+                    sent.push(Packet { from, to, x, y });
+                }
+            }
+
+            let mut stop = None;
+            for packet in sent {
+                self.busy = true;
+                let event = self.send(packet);
+                if traffic.observe(&event) {
+                    stop = stop.or(Some(event));
+                }
+            }
+            if stop.is_some() {
+                return Ok(stop);
+            }
+        }
+
+        self.rounds += 1;
+        self.turn = 0;
+        self.idle = !self.busy;
+        self.busy = false;
+        if self.idle {
+            let wake = self.nat.map(|packet| Packet {
+                from: NAT,
+                to: 0,
+                ..packet
+            });
+            if let Some(packet) = wake {
+                self.hosts[0].queue.push_back((packet.x, packet.y));
+            }
+            let event = Event::Idle {
+                round: self.rounds,
+                wake,
+            };
+            if traffic.observe(&event) {
+                return Ok(Some(event));
+            }
+        }
+        Ok(None)
+    }
+
+    fn send(&mut self, packet: Packet) -> Event {
+        match packet.to {
+            NAT => {
+                self.nat = Some(packet);
+                Event::Sent(packet)
+            }
+            to if to >= 0 && (to as usize) < self.hosts.len() => {
+                let queue = &mut self.hosts[to as usize].queue;
+                queue.push_back((packet.x, packet.y));
+                Event::Sent(packet)
+            }
+            _ => Event::Dropped(packet),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble;
+
+    // Machine 0 starts a packet off down the line, each machine passing it on to the next with
+    // X one more and Y doubled, and the last passing it to the NAT.
+    fn relay(size: usize) -> Vec<i64> {
+        assemble(&format!(
+            "
+                    IN -> [address]
+                    JNZ [address], loop
+                    OUT #1
+                    OUT #0
+                    OUT #1
+            loop:   IN -> [x]
+                    EQ [x], #-1 -> [t]
+                    JNZ [t], loop
+                    IN -> [y]
+                    ADD [address], #1 -> [next]
+                    EQ [next], #{} -> [t]
+                    JZ [t], send
+                    ADD #{}, #0 -> [next]
+            send:   OUT [next]
+                    ADD [x], #1 -> [x]
+                    OUT [x]
+                    MUL [y], #2 -> [y]
+                    OUT [y]
+                    JNZ #1, loop
+            address: .data 0
+            next:   .data 0
+            x:      .data 0
+            y:      .data 0
+            t:      .data 0
+            ",
+            size, NAT
+        ))
+        .unwrap()
+    }
+
+    fn packet(from: i64, to: i64, x: i64, y: i64) -> Packet {
+        Packet { from, to, x, y }
+    }
+
+    #[test]
+    fn relays_through_the_nat() {
+        let mut router = Router::new(&relay(4), 4).unwrap();
+        let mut events = Vec::new();
+        let stop = router
+            .run(&mut |event: &Event| {
+                events.push(*event);
+                matches!(event, Event::Sent(p) if p.to == NAT && p.x > 4)
+            })
+            .unwrap();
+
+        assert_eq!(stop, Some(Event::Sent(packet(3, NAT, 7, 128))));
+        assert_eq!(
+            events,
+            vec![
+                Event::Sent(packet(0, 1, 0, 1)),
+                Event::Sent(packet(1, 2, 1, 2)),
+                Event::Sent(packet(2, 3, 2, 4)),
+                Event::Sent(packet(3, NAT, 3, 8)),
+                Event::Idle {
+                    round: 2,
+                    wake: Some(packet(NAT, 0, 3, 8)),
+                },
+                Event::Sent(packet(0, 1, 4, 16)),
+                Event::Sent(packet(1, 2, 5, 32)),
+                Event::Sent(packet(2, 3, 6, 64)),
+                Event::Sent(packet(3, NAT, 7, 128)),
+            ]
+        );
+        assert_eq!(router.nat(), Some(packet(3, NAT, 7, 128)));
+        assert!(router.queue(0).is_empty());
+    }
+
+    #[test]
+    fn deterministic_and_resumable() {
+        // stopping after every event has to make no difference
+        let log = |stop_every: bool| {
+            let mut router = Router::new(&relay(10), 10).unwrap();
+            let mut events = Vec::new();
+            while events.len() < 40 {
+                let mut observe = |event: &Event| {
+                    events.push(*event);
+                    stop_every || events.len() == 40
+                };
+                router.run(&mut observe).unwrap();
+            }
+            events
+        };
+        let uninterrupted = log(false);
+        assert_eq!(uninterrupted, log(true));
+        assert_eq!(uninterrupted[9], Event::Sent(packet(9, NAT, 9, 1 << 9)));
+    }
+
+    #[test]
+    fn drops_unaddressed_packets_and_stops_when_idle() {
+        // send one packet to an address nobody has, then wait forever
+        let program = assemble(
+            "IN -> [a]\nOUT #7\nOUT [a]\nOUT #0\nloop: IN -> [a]\nJNZ #1, loop\na: .data 0",
+        )
+        .unwrap();
+        let mut router = Router::new(&program, 2).unwrap();
+        let mut events = Vec::new();
+        let stop = router
+            .run(&mut |event: &Event| {
+                events.push(*event);
+                false
+            })
+            .unwrap();
+
+        assert_eq!(stop, None);
+        assert_eq!(
+            events,
+            vec![
+                Event::Dropped(packet(0, 7, 0, 0)),
+                Event::Dropped(packet(1, 7, 1, 0)),
+                Event::Idle {
+                    round: 2,
+                    wake: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn no_machine_at_the_nat() {
+        assert!(Router::new(&relay(255), 255).is_ok());
+        assert_eq!(
+            Router::new(&relay(256), 256).unwrap_err(),
+            "can't have 256 machines, the NAT is at 255"
+        );
+    }
+}
@@ -0,0 +1,418 @@
+use crate::{Machine, Memory};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 4] = b"ICSN";
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    NotASnapshot,
+    UnsupportedVersion(u32),
+    Corrupt(&'static str),
+    // more memory than the machine being restored can hold
+    TooBig { size: u64 },
+}
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::NotASnapshot => write!(f, "not an intcode snapshot"),
+            SnapshotError::UnsupportedVersion(v) => write!(
+                f,
+                "snapshot version {} is not supported (expected {})",
+                v, SNAPSHOT_VERSION
+            ),
+            SnapshotError::Corrupt(why) => write!(f, "corrupt snapshot: {}", why),
+            SnapshotError::TooBig { size } => {
+                write!(
+                    f,
+                    "snapshot memory of {} words doesn't fit this machine",
+                    size
+                )
+            }
+        }
+    }
+}
+impl Error for SnapshotError {}
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+// Everything needed to carry on running a paused machine, plus whatever it had output by then
+// since the machine itself doesn't keep that.
+//
+// On disk, after the magic bytes and a little-endian u32 version, every number is a little-endian
+// 64 bit word: program counter, relative base, steps, memory size, then the memory as runs of
+// non-zero words (count, then start and length and words for each), then the pending input and
+// the output (count, then words for each), then the promoted words (count, then address and low
+// and high halves for each). Version 1 snapshots, from before promotion, stop after the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    program_counter: i64,
+    relative_base: i64,
+    steps: u64,
+    size: u64,
+    runs: Vec<(u64, Vec<i64>)>,
+    input: Vec<i64>,
+    output: Vec<i64>,
+    promoted: Vec<(u64, i128)>,
+}
+impl Snapshot {
+    pub fn output(&self) -> &[i64] {
+        &self.output
+    }
+
+    // A new machine in the same state as the one snapshotted, trace aside. Fails if its memory is
+    // bigger than `M` can hold, e.g. a sparse machine's restored into dense memory.
+    pub fn restore<M: Memory + Default>(&self) -> Result<Machine<M>, SnapshotError> {
+        let mut data = M::default();
+        let fits = usize::try_from(self.size).is_ok_and(|size| data.try_extend(size));
+        if !fits {
+            return Err(SnapshotError::TooBig { size: self.size });
+        }
+        for (start, words) in &self.runs {
+            for (i, word) in words.iter().enumerate() {
+                *data.cell(*start as usize + i) = *word;
+            }
+        }
+        let mut machine = Machine::resume(
+            data,
+            self.program_counter,
+            self.relative_base,
+            self.input.iter().cloned().collect::<VecDeque<i64>>(),
+            self.steps as usize,
+        );
+        let promoted = self
+            .promoted
+            .iter()
+            .map(|&(address, word)| (address as usize, word));
+        machine.set_promoted(promoted.collect());
+        Ok(machine)
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        for word in &[self.program_counter, self.relative_base] {
+            out.write_all(&word.to_le_bytes())?;
+        }
+        for word in &[self.steps, self.size, self.runs.len() as u64] {
+            out.write_all(&word.to_le_bytes())?;
+        }
+        for (start, words) in &self.runs {
+            out.write_all(&start.to_le_bytes())?;
+            write_words(out, words)?;
+        }
+        write_words(out, &self.input)?;
+        write_words(out, &self.output)?;
+        out.write_all(&(self.promoted.len() as u64).to_le_bytes())?;
+        for (address, word) in &self.promoted {
+            out.write_all(&address.to_le_bytes())?;
+            out.write_all(&word.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read(input: &mut dyn Read) -> Result<Self, SnapshotError> {
+        let mut magic = [0; 4];
+        input
+            .read_exact(&mut magic)
+            .map_err(|_| SnapshotError::NotASnapshot)?;
+        if &magic != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let mut version = [0; 4];
+        input.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != 1 && version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let program_counter = read_word(input)?;
+        let relative_base = read_word(input)?;
+        let steps = read_word(input)? as u64;
+        let size = read_word(input)? as u64;
+        let num_runs = read_word(input)? as u64;
+        let mut runs = Vec::new();
+        let mut end = 0;
+        for _ in 0..num_runs {
+            let start = read_word(input)? as u64;
+            let words = read_words(input)?;
+            let run_end = start.checked_add(words.len() as u64);
+            end = match run_end {
+                Some(run_end) if start >= end && run_end <= size => run_end,
+                _ => {
+                    return Err(SnapshotError::Corrupt(
+                        "memory runs out of order or out of bounds",
+                    ))
+                }
+            };
+            runs.push((start, words));
+        }
+        let input_words = read_words(input)?;
+        let output = read_words(input)?;
+        let mut promoted = Vec::new();
+        if version > 1 {
+            let num_promoted = read_word(input)?;
+            if num_promoted < 0 {
+                return Err(SnapshotError::Corrupt("negative length"));
+            }
+            for _ in 0..num_promoted {
+                let address = read_word(input)? as u64;
+                if address >= size {
+                    return Err(SnapshotError::Corrupt("promoted word out of bounds"));
+                }
+                let mut word = [0; 16];
+                input.read_exact(&mut word)?;
+                promoted.push((address, i128::from_le_bytes(word)));
+            }
+        }
+        Ok(Snapshot {
+            program_counter,
+            relative_base,
+            steps,
+            size,
+            runs,
+            input: input_words,
+            output,
+            promoted,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SnapshotError> {
+        Self::read(&mut bytes)
+    }
+}
+
+fn read_word(input: &mut dyn Read) -> io::Result<i64> {
+    let mut word = [0; 8];
+    input.read_exact(&mut word)?;
+    Ok(i64::from_le_bytes(word))
+}
+
+fn read_words(input: &mut dyn Read) -> Result<Vec<i64>, SnapshotError> {
+    let len = read_word(input)?;
+    if len < 0 {
+        return Err(SnapshotError::Corrupt("negative length"));
+    }
+    // don't trust the length enough to allocate it all up front
+    let mut words = Vec::new();
+    for _ in 0..len {
+        words.push(read_word(input)?);
+    }
+    Ok(words)
+}
+
+fn write_words(out: &mut dyn Write, words: &[i64]) -> io::Result<()> {
+    out.write_all(&(words.len() as u64).to_le_bytes())?;
+    for word in words {
+        out.write_all(&word.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+impl<M: Memory> Machine<M> {
+    // `output` is what the machine has output so far, to be handed back on restore.
+    pub fn snapshot(&self, output: &[i64]) -> Snapshot {
+        let data = self.data();
+        let mut runs: Vec<(u64, Vec<i64>)> = Vec::new();
+        for range in data.populated() {
+            let mut in_run = false;
+            for address in range {
+                let word = data.read(address);
+                if word == 0 {
+                    in_run = false;
+                } else if in_run {
+                    runs.last_mut().unwrap().1.push(word);
+                } else {
+                    runs.push((address as u64, vec![word]));
+                    in_run = true;
+                }
+            }
+        }
+        Snapshot {
+            program_counter: self.program_counter(),
+            relative_base: self.relative_base(),
+            steps: self.steps() as u64,
+            size: data.size() as u64,
+            runs,
+            input: self.pending_input().iter().cloned().collect(),
+            output: output.to_vec(),
+            promoted: self
+                .promoted()
+                .iter()
+                .map(|(&address, &word)| (address as u64, word))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Overflow, PagedMemory, Status};
+
+    // day09's quine: outputs a copy of itself, using relative mode and memory past the end
+    fn quine() -> Vec<i64> {
+        vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ]
+    }
+
+    fn run<M: Memory>(machine: &mut Machine<M>, output: &mut Vec<i64>) {
+        while let Status::Output(o) = machine.run_until().unwrap() {
+            output.push(o);
+        }
+    }
+
+    #[test]
+    fn restored_machine_finishes_the_same() {
+        let mut uninterrupted = Machine::new(quine());
+        let mut expected = Vec::new();
+        run(&mut uninterrupted, &mut expected);
+
+        for outputs_before_pause in 0..expected.len() {
+            let mut machine = Machine::new(quine());
+            let mut output = Vec::new();
+            for _ in 0..outputs_before_pause {
+                if let Status::Output(o) = machine.run_until().unwrap() {
+                    output.push(o);
+                }
+            }
+            let bytes = machine.snapshot(&output).to_bytes();
+
+            let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+            let mut restored: Machine = snapshot.restore().unwrap();
+            let mut output = snapshot.output().to_vec();
+            run(&mut restored, &mut output);
+            assert_eq!(output, expected);
+            assert_eq!(restored.steps(), uninterrupted.steps());
+            assert_eq!(restored.data(), uninterrupted.data());
+        }
+    }
+
+    #[test]
+    fn keeps_pending_input_and_sparse_memory() {
+        // read two numbers and output their sum, after writing to a far away address
+        let mut data = vec![
+            1101,
+            1,
+            1,
+            1 << 20,
+            3,
+            100,
+            3,
+            101,
+            1,
+            100,
+            101,
+            102,
+            4,
+            102,
+            99,
+        ];
+        data.resize(103, 0);
+        let mut machine = Machine::with_memory(PagedMemory::from(data));
+        machine.push_input(40);
+        assert_eq!(machine.run_until().unwrap(), Status::NeedsInput);
+        machine.push_input(2);
+
+        let snapshot = machine.snapshot(&[]);
+        let mut restored: Machine<PagedMemory> = Snapshot::from_bytes(&snapshot.to_bytes())
+            .unwrap()
+            .restore()
+            .unwrap();
+        assert_eq!(restored.pending_input(), &VecDeque::from(vec![2]));
+        assert_eq!(restored.data().num_pages(), 2);
+        assert_eq!(restored.data().read(1 << 20), 2);
+        assert_eq!(restored.run_until().unwrap(), Status::Output(42));
+    }
+
+    #[test]
+    fn sparse_machine_far_out() {
+        // the size of memory that a write this far out leaves can only be restored sparsely
+        let high = 1 << 62;
+        let mut data = PagedMemory::from(vec![104, 7, 99]);
+        *data.cell(high) = 5;
+        let machine = Machine::with_memory(data);
+        let snapshot = machine.snapshot(&[]);
+        let bytes = snapshot.to_bytes();
+        assert!(bytes.len() < 200);
+
+        let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+        let restored: Machine<PagedMemory> = snapshot.restore().unwrap();
+        assert_eq!(restored.data().read(high), 5);
+        assert_eq!(restored.data().size(), high + 1);
+        assert!(matches!(
+            snapshot.restore::<Vec<i64>>(),
+            Err(SnapshotError::TooBig { size }) if size == high as u64 + 1
+        ));
+    }
+
+    #[test]
+    fn keeps_promoted_words() {
+        // [16] = [16] * 4 and [17] = [17] * -4, too wide for a word, then output their sum
+        let big = 1 << 62;
+        let data = vec![
+            1002, 16, 4, 16, 1002, 17, -4, 17, 1, 16, 17, 18, 4, 18, 99, 0, big, big, 0,
+        ];
+        let mut machine = Machine::new(data);
+        machine.set_overflow(Overflow::Promote);
+        machine.step().unwrap();
+        machine.step().unwrap();
+        assert_eq!(machine.promoted().len(), 2);
+
+        let snapshot = Snapshot::from_bytes(&machine.snapshot(&[]).to_bytes()).unwrap();
+        let mut restored: Machine = snapshot.restore().unwrap();
+        assert_eq!(restored.promoted(), machine.promoted());
+        restored.set_overflow(Overflow::Promote);
+        assert_eq!(restored.run_until().unwrap(), Status::Output(0));
+    }
+
+    #[test]
+    fn rejects_bad_snapshots() {
+        let mut bytes = Machine::new(quine()).snapshot(&[]).to_bytes();
+        assert!(matches!(
+            Snapshot::from_bytes(b"nope"),
+            Err(SnapshotError::NotASnapshot)
+        ));
+        assert!(matches!(
+            Snapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::Io(_))
+        ));
+        // a run starting so far out that it wraps round to the start
+        let mut wrapping = bytes.clone();
+        let first_run = 4 + 4 + 5 * 8;
+        wrapping[first_run..first_run + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            Snapshot::from_bytes(&wrapping),
+            Err(SnapshotError::Corrupt(_))
+        ));
+        // a run with a negative length
+        let mut negative = bytes.clone();
+        let first_run_len = first_run + 8;
+        negative[first_run_len..first_run_len + 8].copy_from_slice(&(-1i64).to_le_bytes());
+        assert!(matches!(
+            Snapshot::from_bytes(&negative),
+            Err(SnapshotError::Corrupt("negative length"))
+        ));
+        bytes[4] = 3;
+        assert!(matches!(
+            Snapshot::from_bytes(&bytes),
+            Err(SnapshotError::UnsupportedVersion(3))
+        ));
+    }
+}
@@ -0,0 +1,608 @@
+use crate::opcode::*;
+use crate::VmError;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+// What an expression is in terms of: a cell's value when the program started, the nth value
+// the program read, or whatever the nth read through a symbolic address found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Var {
+    Cell(usize),
+    Input(usize),
+    Load(usize),
+}
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Var::Cell(address) => write!(f, "[{}]", address),
+            Var::Input(n) => write!(f, "in{}", n),
+            Var::Load(n) => write!(f, "load{}", n),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Const(i64),
+    Var(Var),
+    Add(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    // 1 or 0, like LT and EQ
+    LessThan(Box<Expr>, Box<Expr>),
+    Equals(Box<Expr>, Box<Expr>),
+}
+impl Expr {
+    // Sums and products are kept as `a*x + b*y + ... + c` while they are affine, so a long
+    // chain of ADDs and MULs stays as small as the function it computes.
+    pub fn sum(a: Expr, b: Expr) -> Expr {
+        let sum = a.affine().zip(b.affine()).and_then(|(a, b)| a.plus(&b));
+        sum.map_or_else(|| Expr::Add(Box::new(a), Box::new(b)), Expr::from)
+    }
+
+    pub fn product(a: Expr, b: Expr) -> Expr {
+        let product = match (a.constant(), b.constant()) {
+            (Some(k), _) => b.affine().and_then(|b| b.times(k)),
+            (_, Some(k)) => a.affine().and_then(|a| a.times(k)),
+            _ => None,
+        };
+        product.map_or_else(|| Expr::Mul(Box::new(a), Box::new(b)), Expr::from)
+    }
+
+    pub fn less_than(a: Expr, b: Expr) -> Expr {
+        match (a.constant(), b.constant()) {
+            (Some(a), Some(b)) => Expr::Const((a < b) as i64),
+            _ => Expr::LessThan(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn equals(a: Expr, b: Expr) -> Expr {
+        match (a.constant(), b.constant()) {
+            (Some(a), Some(b)) => Expr::Const((a == b) as i64),
+            _ if a == b => Expr::Const(1),
+            _ => Expr::Equals(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn constant(&self) -> Option<i64> {
+        match self {
+            Expr::Const(k) => Some(*k),
+            _ => None,
+        }
+    }
+
+    // `None` if the expression isn't affine, or its coefficients don't fit in a word.
+    pub fn affine(&self) -> Option<Affine> {
+        match self {
+            Expr::Const(k) => Some(Affine {
+                constant: *k,
+                terms: BTreeMap::new(),
+            }),
+            Expr::Var(var) => Some(Affine {
+                constant: 0,
+                terms: vec![(*var, 1)].into_iter().collect(),
+            }),
+            Expr::Add(a, b) => a.affine()?.plus(&b.affine()?),
+            Expr::Mul(a, b) => match (a.constant(), b.constant()) {
+                (Some(k), _) => b.affine()?.times(k),
+                (_, Some(k)) => a.affine()?.times(k),
+                _ => None,
+            },
+            Expr::LessThan(..) | Expr::Equals(..) => None,
+        }
+    }
+
+    // The value with `var` giving the value of each variable, wrapping like `Overflow::Wrap`.
+    pub fn eval(&self, var: &dyn Fn(Var) -> i64) -> i64 {
+        match self {
+            Expr::Const(k) => *k,
+            Expr::Var(v) => var(*v),
+            Expr::Add(a, b) => a.eval(var).wrapping_add(b.eval(var)),
+            Expr::Mul(a, b) => a.eval(var).wrapping_mul(b.eval(var)),
+            Expr::LessThan(a, b) => (a.eval(var) < b.eval(var)) as i64,
+            Expr::Equals(a, b) => (a.eval(var) == b.eval(var)) as i64,
+        }
+    }
+
+    // tighter binding first, so parentheses only go where they are needed
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Const(_) | Expr::Var(_) => 3,
+            Expr::Mul(..) => 2,
+            Expr::Add(..) => 1,
+            Expr::LessThan(..) | Expr::Equals(..) => 0,
+        }
+    }
+}
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = |e: &Expr, f: &mut fmt::Formatter| {
+            if e.precedence() < self.precedence() {
+                write!(f, "({})", e)
+            } else {
+                write!(f, "{}", e)
+            }
+        };
+        let binary = |a: &Expr, op: &str, b: &Expr, f: &mut fmt::Formatter| {
+            operand(a, f)?;
+            write!(f, " {} ", op)?;
+            operand(b, f)
+        };
+        match self {
+            Expr::Const(k) => write!(f, "{}", k),
+            Expr::Var(var) => write!(f, "{}", var),
+            Expr::Add(a, b) => match b.constant() {
+                Some(k) if k < 0 && k != i64::MIN => binary(a, "-", &Expr::Const(-k), f),
+                _ => binary(a, "+", b, f),
+            },
+            Expr::Mul(a, b) => binary(a, "*", b, f),
+            Expr::LessThan(a, b) => binary(a, "<", b, f),
+            Expr::Equals(a, b) => binary(a, "==", b, f),
+        }
+    }
+}
+impl From<Var> for Expr {
+    fn from(var: Var) -> Self {
+        Expr::Var(var)
+    }
+}
+
+// `constant + sum of coefficient * variable`, with no zero coefficients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Affine {
+    pub constant: i64,
+    pub terms: BTreeMap<Var, i64>,
+}
+impl Affine {
+    pub fn coefficient(&self, var: Var) -> i64 {
+        *self.terms.get(&var).unwrap_or(&0)
+    }
+
+    fn plus(&self, other: &Affine) -> Option<Affine> {
+        let mut sum = self.clone();
+        sum.constant = sum.constant.checked_add(other.constant)?;
+        for (var, k) in &other.terms {
+            let total = sum.coefficient(*var).checked_add(*k)?;
+            if total == 0 {
+                sum.terms.remove(var);
+            } else {
+                sum.terms.insert(*var, total);
+            }
+        }
+        Some(sum)
+    }
+
+    fn times(&self, k: i64) -> Option<Affine> {
+        if k == 0 {
+            return Some(Affine {
+                constant: 0,
+                terms: BTreeMap::new(),
+            });
+        }
+        let terms = self
+            .terms
+            .iter()
+            .map(|(var, c)| Some((*var, c.checked_mul(k)?)));
+        Some(Affine {
+            constant: self.constant.checked_mul(k)?,
+            terms: terms.collect::<Option<_>>()?,
+        })
+    }
+}
+impl From<Affine> for Expr {
+    fn from(affine: Affine) -> Self {
+        let terms = affine.terms.into_iter().map(|(var, k)| match k {
+            1 => Expr::Var(var),
+            _ => Expr::Mul(Box::new(Expr::Const(k)), Box::new(Expr::Var(var))),
+        });
+        let constant = Some(affine.constant).filter(|&c| c != 0).map(Expr::Const);
+        terms
+            .chain(constant)
+            .reduce(|sum, term| Expr::Add(Box::new(sum), Box::new(term)))
+            .unwrap_or(Expr::Const(0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolicError {
+    // the program failed in a way that doesn't depend on the symbolic cells
+    Vm(VmError),
+    // `what` at the instruction at `address` depends on the symbolic cells, e.g. a branch
+    Depends { address: i64, what: &'static str },
+}
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::Vm(e) => write!(f, "{}", e),
+            SymbolicError::Depends { address, what } => write!(
+                f,
+                "the {} of the instruction at address {} depends on the symbolic cells",
+                what, address
+            ),
+        }
+    }
+}
+impl Error for SymbolicError {}
+impl From<VmError> for SymbolicError {
+    fn from(e: VmError) -> Self {
+        SymbolicError::Vm(e)
+    }
+}
+
+// What a program computed, in terms of the cells it was run over and whatever it read.
+#[derive(Debug, Clone)]
+pub struct SymbolicRun {
+    memory: Vec<Expr>,
+    outputs: Vec<Expr>,
+    loads: Vec<Expr>,
+    steps: usize,
+}
+impl SymbolicRun {
+    pub fn outputs(&self) -> &[Expr] {
+        &self.outputs
+    }
+
+    // The value left in `address` when the program halted.
+    pub fn cell(&self, address: usize) -> Expr {
+        self.memory.get(address).cloned().unwrap_or(Expr::Const(0))
+    }
+
+    // The address `Var::Load(n)` was read from.
+    pub fn load_address(&self, n: usize) -> &Expr {
+        &self.loads[n]
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+}
+
+struct Interpreter {
+    memory: Vec<Expr>,
+    // the memory with symbolic cells as 0, for decoding
+    concrete: Vec<i64>,
+    // the addresses of reads through symbolic addresses
+    loads: Vec<Expr>,
+}
+impl Interpreter {
+    fn load(&self, address: usize) -> Expr {
+        self.memory.get(address).cloned().unwrap_or(Expr::Const(0))
+    }
+
+    // Like the machine's memory, this reaches as far as any cell loaded or written, which is
+    // where running off the end halts.
+    fn grow(&mut self, address: usize) {
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, Expr::Const(0));
+            self.concrete.resize(address + 1, 0);
+        }
+    }
+
+    fn store(&mut self, address: usize, value: Expr) {
+        self.grow(address);
+        self.concrete[address] = value.constant().unwrap_or(0);
+        self.memory[address] = value;
+    }
+
+    // the address of the cell an operand refers to
+    fn address(&self, addr: &Addr) -> Result<Expr, SymbolicError> {
+        let address = match addr {
+            Addr::Immediate(Imm(i)) => return Ok(Expr::Const(*i)),
+            Addr::Position(Pos(i)) => self.load(*i as usize),
+            Addr::Relative(Rel(i, relative_base)) => match self.load(*i as usize).constant() {
+                // as on the machine, a relative address that doesn't fit in a word traps
+                Some(offset) => Expr::Const(
+                    offset
+                        .checked_add(*relative_base)
+                        .ok_or(VmError::AddressOverflow { address: *i })?,
+                ),
+                None => Expr::sum(self.load(*i as usize), Expr::Const(*relative_base)),
+            },
+        };
+        match address.constant() {
+            Some(address) if address < 0 => Err(VmError::NegativeAddress { address }.into()),
+            _ => Ok(address),
+        }
+    }
+
+    // Reading through a symbolic address gives a new `Load` variable, which is fine as long as
+    // nothing comes to depend on it.
+    fn read(&mut self, addr: &Addr) -> Result<Expr, SymbolicError> {
+        let address = self.address(addr)?;
+        Ok(match address.constant() {
+            Some(address) => self.load(address as usize),
+            None => {
+                self.loads.push(address);
+                Expr::Var(Var::Load(self.loads.len() - 1))
+            }
+        })
+    }
+
+    fn write(
+        &mut self,
+        addr: &Addr,
+        program_counter: i64,
+        value: Expr,
+    ) -> Result<(), SymbolicError> {
+        if let Addr::Immediate(Imm(i)) = addr {
+            return Err(VmError::WriteToImmediate { address: *i }.into());
+        }
+        let address = self
+            .address(addr)?
+            .constant()
+            .ok_or(SymbolicError::Depends {
+                address: program_counter,
+                what: "destination",
+            })?;
+        self.store(address as usize, value);
+        Ok(())
+    }
+
+    fn known(
+        &mut self,
+        addr: &Addr,
+        program_counter: i64,
+        what: &'static str,
+    ) -> Result<i64, SymbolicError> {
+        let value = self.read(addr)?;
+        value.constant().ok_or(SymbolicError::Depends {
+            address: program_counter,
+            what,
+        })
+    }
+}
+
+// Run the standard instruction set over `data` with the cells at `symbolic` standing for
+// whatever values they might hold, and every input symbolic too. This works as long as control
+// flow and where things are written don't depend on them, which is the case for day02-style
+// programs that just add and multiply their operands; anything else is reported as `Depends`.
+// Like `run_program`, arithmetic overflow traps, and `fuel` bounds how many instructions run.
+pub fn run_symbolic(
+    data: &[i64],
+    symbolic: &[usize],
+    fuel: usize,
+) -> Result<SymbolicRun, SymbolicError> {
+    let mut interpreter = Interpreter {
+        memory: data.iter().map(|&word| Expr::Const(word)).collect(),
+        concrete: data.to_vec(),
+        loads: Vec::new(),
+    };
+    for &address in symbolic {
+        interpreter.store(address, Expr::Var(Var::Cell(address)));
+    }
+    let mut program_counter: i64 = 0;
+    let mut relative_base = 0;
+    let mut inputs = 0;
+    let mut outputs = Vec::new();
+    let mut steps = 0;
+
+    while program_counter >= 0 && (program_counter as usize) < interpreter.memory.len() {
+        if interpreter
+            .load(program_counter as usize)
+            .constant()
+            .is_none()
+        {
+            return Err(SymbolicError::Depends {
+                address: program_counter,
+                what: "opcode",
+            });
+        }
+        if steps == fuel {
+            return Err(VmError::OutOfFuel {
+                address: program_counter,
+                steps,
+            }
+            .into());
+        }
+        let opcode = OpCode::try_from(CurrentState {
+            data: &interpreter.concrete,
+            program_counter,
+            relative_base,
+        })?;
+        let pc = program_counter;
+        let mut next = pc + opcode.numFields() as i64 + 1;
+        match &opcode {
+            OpCode::Add {
+                opAddr1,
+                opAddr2,
+                destAddr,
+            }
+            | OpCode::Mult {
+                opAddr1,
+                opAddr2,
+                destAddr,
+            } => {
+                let (a, b) = (interpreter.read(opAddr1)?, interpreter.read(opAddr2)?);
+                let value = match (&opcode, a.constant(), b.constant()) {
+                    // concrete arithmetic traps on overflow just like the machine
+                    (OpCode::Add { .. }, Some(a), Some(b)) => {
+                        Expr::Const(a.checked_add(b).ok_or(VmError::Overflow { address: pc })?)
+                    }
+                    (_, Some(a), Some(b)) => {
+                        Expr::Const(a.checked_mul(b).ok_or(VmError::Overflow { address: pc })?)
+                    }
+                    (OpCode::Add { .. }, _, _) => Expr::sum(a, b),
+                    _ => Expr::product(a, b),
+                };
+                interpreter.write(destAddr, pc, value)?;
+            }
+            OpCode::SetIfLt {
+                opAddr1,
+                opAddr2,
+                destAddr,
+            } => {
+                let (a, b) = (interpreter.read(opAddr1)?, interpreter.read(opAddr2)?);
+                interpreter.write(destAddr, pc, Expr::less_than(a, b))?;
+            }
+            OpCode::SetIfEq {
+                opAddr1,
+                opAddr2,
+                destAddr,
+            } => {
+                let (a, b) = (interpreter.read(opAddr1)?, interpreter.read(opAddr2)?);
+                interpreter.write(destAddr, pc, Expr::equals(a, b))?;
+            }
+            OpCode::Read { destAddr } => {
+                interpreter.write(destAddr, pc, Expr::Var(Var::Input(inputs)))?;
+                inputs += 1;
+            }
+            OpCode::Write { opAddr1 } => outputs.push(interpreter.read(opAddr1)?),
+            OpCode::JumpIf { boolAddr, jumpAddr } | OpCode::JumpIfNot { boolAddr, jumpAddr } => {
+                let condition = interpreter.known(boolAddr, pc, "condition")?;
+                if (condition != 0) == matches!(opcode, OpCode::JumpIf { .. }) {
+                    next = interpreter.known(jumpAddr, pc, "jump target")?;
+                    if next < 0 {
+                        return Err(VmError::NegativeAddress { address: next }.into());
+                    }
+                }
+            }
+            OpCode::SetRelBase { relAddrChange } => {
+                let change = interpreter.known(relAddrChange, pc, "relative base")?;
+                relative_base = relative_base
+                    .checked_add(change)
+                    .ok_or(VmError::AddressOverflow { address: pc + 1 })?;
+            }
+            OpCode::Halt => next = -1,
+            // only the standard instruction set is decoded
+            OpCode::Custom { instruction, .. } => {
+                return Err(VmError::InvalidOpCode {
+                    address: pc,
+                    opcode: instruction.code,
+                }
+                .into())
+            }
+        }
+        program_counter = next;
+        steps += 1;
+    }
+    Ok(SymbolicRun {
+        memory: interpreter.memory,
+        outputs,
+        loads: interpreter.loads,
+        steps,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble, run_program, Machine, Memory, Program};
+
+    // a day02 puzzle input
+    const DAY02: &str = "1,0,0,3,1,1,2,3,1,3,4,3,1,5,0,3,2,10,1,19,1,6,19,23,1,23,13,27,2,6,27,31,1,5,31,35,2,10,35,39,1,6,39,43,1,13,43,47,2,47,6,51,1,51,5,55,1,55,6,59,2,59,10,63,1,63,6,67,2,67,10,71,1,71,9,75,2,75,10,79,1,79,5,83,2,10,83,87,1,87,6,91,2,9,91,95,1,95,5,99,1,5,99,103,1,103,10,107,1,9,107,111,1,6,111,115,1,115,5,119,1,10,119,123,2,6,123,127,2,127,6,131,1,131,2,135,1,10,135,0,99,2,0,14,0";
+
+    #[test]
+    fn day02_is_affine_in_noun_and_verb() {
+        let data: Vec<i64> = DAY02.parse::<Program>().unwrap().into();
+        let run = run_symbolic(&data, &[1, 2], 1000).unwrap();
+        let result = run.cell(0);
+        assert_eq!(result.to_string(), "196608 * [1] + [2] + 423116");
+
+        // the first instruction reads through noun and verb, but its result is overwritten
+        assert_eq!(run.load_address(0), &Expr::Var(Var::Cell(1)));
+        let affine = result.affine().unwrap();
+        assert_eq!(affine.coefficient(Var::Cell(1)), 196608);
+        assert_eq!(affine.coefficient(Var::Load(0)), 0);
+
+        for &(noun, verb) in &[(12, 2), (98, 20), (0, 99)] {
+            let mut data = data.clone();
+            data[1] = noun;
+            data[2] = verb;
+            let cells = |var| match var {
+                Var::Cell(1) => noun,
+                Var::Cell(2) => verb,
+                _ => panic!("{} isn't in the result", var),
+            };
+            let mut machine = Machine::new(data);
+            machine.run_until().unwrap();
+            assert_eq!(result.eval(&cells), machine.data().read(0));
+        }
+    }
+
+    #[test]
+    fn inputs_and_comparisons() {
+        let program = assemble(
+            "
+                    IN -> [x]
+                    MUL [x], #3 -> [y]
+                    ADD [y], #-1 -> [y]
+                    OUT [y]
+                    LT [y], #10 -> [y]
+                    OUT [y]
+                    MUL [x], [x] -> [x]
+                    OUT [x]
+                    HLT
+            x:      .data 0
+            y:      .data 0
+            ",
+        )
+        .unwrap();
+        let run = run_symbolic(&program, &[], 1000).unwrap();
+        let outputs: Vec<String> = run.outputs().iter().map(|o| o.to_string()).collect();
+        assert_eq!(
+            outputs,
+            vec!["3 * in0 - 1", "3 * in0 - 1 < 10", "in0 * in0"]
+        );
+        let input = |_| 4;
+        let values: Vec<i64> = run.outputs().iter().map(|o| o.eval(&input)).collect();
+        assert_eq!(values, run_program(vec![4], program).unwrap());
+    }
+
+    #[test]
+    fn control_flow_has_to_be_concrete() {
+        // output the input, or nothing if it's zero
+        let program = vec![3, 9, 1005, 9, 7, 99, 0, 4, 9, 99];
+        assert_eq!(
+            run_symbolic(&program, &[], 100).unwrap_err(),
+            SymbolicError::Depends {
+                address: 2,
+                what: "condition",
+            }
+        );
+        assert_eq!(
+            run_symbolic(&[1105, 1, 0], &[], 100)
+                .unwrap_err()
+                .to_string(),
+            "ran out of fuel before the instruction at address 0, after 100 steps"
+        );
+        assert_eq!(
+            run_symbolic(&[1, 0, 0, 5, 99, 0], &[0], 100).unwrap_err(),
+            SymbolicError::Depends {
+                address: 0,
+                what: "opcode",
+            }
+        );
+        // reading doesn't move where memory ends, so a read through the input can't change
+        // where running off it halts
+        let run = run_symbolic(&[3, 3, 4, 0], &[], 100).unwrap();
+        assert_eq!(run.outputs(), &[Expr::Var(Var::Load(0))]);
+        assert_eq!(run.load_address(0), &Expr::Var(Var::Input(0)));
+        assert_eq!(
+            run_symbolic(&[1101, 1, 1, 0, 99], &[3], 100).unwrap_err(),
+            SymbolicError::Depends {
+                address: 0,
+                what: "destination",
+            }
+        );
+    }
+
+    #[test]
+    fn address_overflow() {
+        // the same programs the machine traps on
+        for program in &[
+            vec![109, i64::MAX, 109, 1, 99],
+            vec![109, i64::MAX, 204, 1, 99],
+            vec![109, i64::MIN, 204, -1, 99],
+        ] {
+            assert_eq!(
+                run_symbolic(program, &[], 100).unwrap_err(),
+                VmError::AddressOverflow { address: 3 }.into()
+            );
+            assert_eq!(
+                run_program(vec![], program.clone()).unwrap_err(),
+                VmError::AddressOverflow { address: 3 }
+            );
+        }
+    }
+}
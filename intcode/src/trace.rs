@@ -0,0 +1,258 @@
+use crate::{render_instruction, Memory, OpCode};
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub address: usize,
+    pub old: i64,
+    pub new: i64,
+}
+
+// Everything one executed instruction did.
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    pub step: usize,
+    pub program_counter: i64,
+    pub opcode: OpCode,
+    pub instruction: String,
+    // values of `opcode.sources()`, in order
+    pub operands: Vec<i64>,
+    pub write: Option<MemoryWrite>,
+    // (old, new), only if the instruction changed it
+    pub relative_base: Option<(i64, i64)>,
+    pub input: Option<i64>,
+    pub output: Option<i64>,
+}
+impl TraceEntry {
+    // The half of an entry that can be filled in before `opcode` executes.
+    pub(crate) fn before(
+        step: usize,
+        opcode: OpCode,
+        data: &dyn Memory,
+        program_counter: i64,
+        relative_base: i64,
+        input: Option<i64>,
+    ) -> Self {
+        let operands = opcode
+            .sources()
+            .into_iter()
+            .map(|addr| addr.resolve(data).map(|a| data.read(a)).unwrap_or(0))
+            .collect();
+        let write = opcode
+            .dest()
+            .and_then(|addr| addr.resolve(data).ok())
+            .map(|address| MemoryWrite {
+                address,
+                old: data.read(address),
+                new: data.read(address),
+            });
+        let input = match opcode {
+            OpCode::Read { .. } => input,
+            _ => None,
+        };
+        TraceEntry {
+            step,
+            program_counter,
+            instruction: render_instruction(&opcode, data),
+            opcode,
+            operands,
+            write,
+            relative_base: Some((relative_base, relative_base)),
+            input,
+            output: None,
+        }
+    }
+
+    pub(crate) fn after(
+        mut self,
+        data: &dyn Memory,
+        relative_base: i64,
+        output: Option<i64>,
+    ) -> Self {
+        if let Some(write) = self.write.as_mut() {
+            write.new = data.read(write.address);
+        }
+        self.relative_base = match self.relative_base {
+            Some((old, _)) if old != relative_base => Some((old, relative_base)),
+            _ => None,
+        };
+        self.output = output;
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        let operands: Vec<String> = self.operands.iter().map(|o| o.to_string()).collect();
+        let write = match self.write {
+            Some(w) => format!(
+                "{{\"address\":{},\"old\":{},\"new\":{}}}",
+                w.address, w.old, w.new
+            ),
+            None => String::from("null"),
+        };
+        let relative_base = match self.relative_base {
+            Some((old, new)) => format!("{{\"old\":{},\"new\":{}}}", old, new),
+            None => String::from("null"),
+        };
+        let number = |n: Option<i64>| n.map_or(String::from("null"), |n| n.to_string());
+        format!(
+            "{{\"step\":{},\"pc\":{},\"op\":\"{}\",\"instruction\":\"{}\",\"operands\":[{}],\"write\":{},\"relative_base\":{},\"input\":{},\"output\":{}}}",
+            self.step,
+            self.program_counter,
+            self.opcode.mnemonic(),
+            self.instruction,
+            operands.join(","),
+            write,
+            relative_base,
+            number(self.input),
+            number(self.output),
+        )
+    }
+}
+
+// The log of a traced run, starting from the moment tracing was turned on.
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    entries: Vec<TraceEntry>,
+}
+impl Trace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &Vec<TraceEntry> {
+        &self.entries
+    }
+
+    pub(crate) fn push(&mut self, entry: TraceEntry) {
+        self.entries.push(entry);
+    }
+
+    // One JSON object per executed instruction.
+    pub fn write_json_lines(&self, out: &mut dyn Write) -> io::Result<()> {
+        for entry in &self.entries {
+            writeln!(out, "{}", entry.to_json())?;
+        }
+        Ok(())
+    }
+
+    // Apply the first `steps` writes to `data`, which must be memory as it was when tracing
+    // started.
+    pub fn replay(&self, data: &mut dyn Memory, steps: usize) {
+        for entry in self.entries.iter().take(steps) {
+            if let Some(write) = entry.write {
+                *data.cell(write.address) = write.new;
+            }
+        }
+    }
+
+    // The first step at which two runs executed something different, if any.
+    pub fn first_divergence(&self, other: &Trace) -> Option<usize> {
+        let same = |a: &TraceEntry, b: &TraceEntry| {
+            a.program_counter == b.program_counter
+                && a.instruction == b.instruction
+                && a.operands == b.operands
+                && a.write == b.write
+                && a.relative_base == b.relative_base
+                && a.input == b.input
+                && a.output == b.output
+        };
+        let diverged = self
+            .entries
+            .iter()
+            .zip(other.entries.iter())
+            .position(|(a, b)| !same(a, b));
+        match diverged {
+            Some(step) => Some(step),
+            None if self.entries.len() != other.entries.len() => {
+                Some(self.entries.len().min(other.entries.len()))
+            }
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{assemble, Machine, Status};
+
+    fn program() -> Vec<i64> {
+        assemble(
+            "
+                    IN -> [n]
+                    ARB #100
+            loop:   ADD [n], #-1 -> [n]
+                    MUL [n], #2 -> rb+0
+                    OUT rb+0
+                    JNZ [n], loop
+                    HLT
+            n:      .data 0
+            ",
+        )
+        .unwrap()
+    }
+
+    fn traced_run(input: i64) -> Machine {
+        let mut machine = Machine::new(program());
+        machine.start_trace();
+        machine.push_input(input);
+        while machine.run_until().unwrap() != Status::Halted {}
+        machine
+    }
+
+    #[test]
+    fn records_instructions() {
+        let machine = traced_run(2);
+        let entries = machine.trace().unwrap().entries();
+        assert_eq!(entries.len(), 11);
+        assert_eq!(
+            entries[0].to_json(),
+            r#"{"step":0,"pc":0,"op":"IN","instruction":"IN -> [18]","operands":[],"write":{"address":18,"old":0,"new":2},"relative_base":null,"input":2,"output":null}"#
+        );
+        assert_eq!(
+            entries[1].to_json(),
+            r#"{"step":1,"pc":2,"op":"ARB","instruction":"ARB #100","operands":[100],"write":null,"relative_base":{"old":0,"new":100},"input":null,"output":null}"#
+        );
+        assert_eq!(entries[4].output, Some(2));
+        assert_eq!(entries[4].operands, vec![2]);
+
+        let mut out = Vec::new();
+        machine.trace().unwrap().write_json_lines(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 11);
+    }
+
+    #[test]
+    fn replay_reconstructs_memory() {
+        let traced = traced_run(3);
+        let trace = traced.trace().unwrap();
+        for steps in 0..trace.entries().len() {
+            let mut machine = Machine::new(program());
+            machine.push_input(3);
+            for _ in 0..steps {
+                machine.step().unwrap();
+            }
+            let mut replayed = program();
+            trace.replay(&mut replayed, steps);
+            replayed.resize(machine.data().len(), 0);
+            assert_eq!(&replayed, machine.data());
+        }
+    }
+
+    #[test]
+    fn diff_runs() {
+        let a = traced_run(2);
+        let b = traced_run(3);
+        assert_eq!(
+            a.trace().unwrap().first_divergence(a.trace().unwrap()),
+            None
+        );
+        assert_eq!(
+            a.trace().unwrap().first_divergence(b.trace().unwrap()),
+            Some(0)
+        );
+
+        let c = traced_run(2);
+        let mut shorter = c.trace().unwrap().clone();
+        shorter.entries.pop();
+        assert_eq!(a.trace().unwrap().first_divergence(&shorter), Some(10));
+    }
+}